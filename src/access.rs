@@ -0,0 +1,232 @@
+use crate::correlation::RequestIds;
+use crate::requests::ApiGatewayRequestType;
+use crate::responses::ApiGatewayResponseType;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use time::OffsetDateTime;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+
+/// The format of the access log, written to standard output with a line per request.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// No access log is written.
+    #[default]
+    Off,
+    /// The Common Log Format, followed by the host, target registry, and duration.
+    Common,
+    /// The Combined Log Format, followed by the host, target registry, and duration.
+    Combined,
+    /// A JSON object per line.
+    Json,
+}
+
+impl AccessLogFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Common => "common",
+            Self::Combined => "combined",
+            Self::Json => "json",
+        }
+    }
+
+    /// Write a line to the access log on standard output, building the entry only if enabled.
+    pub fn write(&self, f: impl FnOnce() -> AccessLogEntry) {
+        if *self != Self::Off
+            && let Some(line) = f().format(*self)
+        {
+            println!("{line}");
+        }
+    }
+}
+
+impl Display for AccessLogFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Self::Off,
+            "common" | "clf" => Self::Common,
+            "combined" => Self::Combined,
+            "json" => Self::Json,
+            _ => {
+                return Err(format!(
+                    "unknown access log format {s:?}; expected off, common, combined, or json"
+                ));
+            }
+        })
+    }
+}
+
+/// Who requested what through the vanity domain, and where they were sent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccessLogEntry {
+    /// When the request was received, in RFC 3339 format.
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub time: SystemTime,
+    /// The client's address, as per [ApiGatewayRequestType::client_ip].
+    pub client_ip: Option<IpAddr>,
+    pub method: String,
+    pub host: Option<String>,
+    pub path: String,
+    /// The query string, without the leading `?`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub query: String,
+    pub protocol: String,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+    pub status: u16,
+    /// The size of the response body in bytes.
+    pub bytes: usize,
+    /// The host of the registry the request was redirected to.
+    pub registry: Option<String>,
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    pub request_id: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Describe a request received at the given time through the given number of trusted
+    /// proxies, its response, and the registry it was redirected to, if any.
+    pub fn new(
+        req: &ApiGatewayRequestType,
+        resp: &ApiGatewayResponseType,
+        registry: Option<&str>,
+        ids: &RequestIds,
+        trusted_proxies: usize,
+        time: SystemTime,
+        duration: Duration,
+    ) -> Self {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(Into::into)
+        };
+
+        Self {
+            time,
+            client_ip: req.client_ip(trusted_proxies),
+            method: req.method().to_string(),
+            host: req.host().map(Into::into),
+            path: req.path().cloned().unwrap_or_else(|| "/".into()),
+            query: req.query_string(),
+            protocol: req.protocol().unwrap_or("HTTP/1.1").into(),
+            user_agent: header("User-Agent"),
+            referer: header("Referer"),
+            status: u16::try_from(resp.status_code()).unwrap_or_default(),
+            bytes: resp.body().map(|body| body.len()).unwrap_or_default(),
+            registry: registry.map(Into::into),
+            duration,
+            request_id: ids.primary().map(Into::into),
+        }
+    }
+
+    /// Format the entry as a line of the access log, or `None` if it is [off](AccessLogFormat::Off).
+    pub fn format(&self, format: AccessLogFormat) -> Option<String> {
+        match format {
+            AccessLogFormat::Off => None,
+            AccessLogFormat::Common => Some(format!("{} {}", self.common(), self.extension())),
+            AccessLogFormat::Combined => Some(format!(
+                "{} {} {} {}",
+                self.common(),
+                quote(self.referer.as_deref()),
+                quote(self.user_agent.as_deref()),
+                self.extension()
+            )),
+            AccessLogFormat::Json => serde_json::to_string(self).ok(),
+        }
+    }
+
+    /// The fields of the Common Log Format.
+    fn common(&self) -> String {
+        let target = if self.query.is_empty() {
+            self.path.clone()
+        } else {
+            format!("{}?{}", self.path, self.query)
+        };
+
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            self.client_ip
+                .map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+            clf_time(self.time),
+            self.method,
+            escape(&target),
+            self.protocol,
+            self.status,
+            if self.bytes == 0 {
+                "-".to_string()
+            } else {
+                self.bytes.to_string()
+            }
+        )
+    }
+
+    /// The fields following those of the standard formats.
+    fn extension(&self) -> String {
+        format!(
+            "{} {} {:.3}",
+            quote(self.host.as_deref()),
+            quote(self.registry.as_deref()),
+            self.duration.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Quote a field, escaping quotes and control characters, or `-` if there is none.
+fn quote(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", escape(value)),
+        None => "-".into(),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|c| match c {
+            '"' | '\\' => vec!['\\', c],
+            c if c.is_control() => format!("\\x{:02x}", c as u32).chars().collect(),
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// The time as in the Common Log Format, such as `10/Oct/2000:13:55:36 +0000`.
+fn clf_time(time: SystemTime) -> String {
+    const CLF: &[FormatItem<'_>] = format_description!(
+        "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+    );
+
+    OffsetDateTime::from(time).format(CLF).unwrap_or_default()
+}
+
+/// The time in RFC 3339 format with milliseconds, such as `2000-10-10T13:55:36.000Z`.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    const RFC3339_MILLIS: &[FormatItem<'_>] =
+        format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z");
+
+    OffsetDateTime::from(time)
+        .format(RFC3339_MILLIS)
+        .unwrap_or_default()
+}
+
+fn serialize_rfc3339<S: serde::Serializer>(time: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&rfc3339(*time))
+}
+
+fn serialize_millis<S: serde::Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,211 @@
+use crate::access::rfc3339;
+use crate::registry::{Endpoint, Reference};
+use aws_lambda_events::http::Method;
+use bon::Builder;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// The interval in seconds at which pull summaries are written unless one is configured.
+pub const DEFAULT_ANALYTICS_INTERVAL: u64 = 300;
+
+/// The most images [PullAnalytics] counts pulls of between flushes, bounding its memory.
+pub const PULL_ANALYTICS_MAX_IMAGES: usize = 10_000;
+
+/// Whether and how often to summarize the images pulled through the vanity domain.
+#[derive(Debug, Clone, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyticsConfig {
+    /// Whether to count manifest pulls by repository and reference.
+    #[builder(default)]
+    pub enabled: bool,
+    /// The interval in seconds at which to write pull summaries to standard output.
+    #[builder(default = DEFAULT_ANALYTICS_INTERVAL)]
+    pub interval: u64,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// How often an image was requested during a period.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct PullCount {
+    /// `GET` requests for the manifest, each the start of a pull.
+    pub pulls: u64,
+    /// `HEAD` requests for the manifest, with which clients check whether their copy is current.
+    pub checks: u64,
+}
+
+/// The pulls counted since the previous summary.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PullSummary {
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// The counts by repository and reference, ordered by repository and then reference.
+    pub images: Vec<(String, Reference, PullCount)>,
+    /// Requests for images which were not counted because [PULL_ANALYTICS_MAX_IMAGES] were
+    /// already counted.
+    pub dropped: u64,
+}
+
+impl PullSummary {
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty() && self.dropped == 0
+    }
+
+    /// The summary as structured events: one per image, and one for the period as a whole.
+    pub fn events(&self) -> Vec<Value> {
+        let (start, end) = (rfc3339(self.start), rfc3339(self.end));
+
+        let mut events = self
+            .images
+            .iter()
+            .map(|(repository, reference, count)| {
+                let mut event = json!({
+                    "event": "image_pulls",
+                    "period_start": start,
+                    "period_end": end,
+                    "repository": repository,
+                    "pulls": count.pulls,
+                    "checks": count.checks,
+                });
+
+                match reference {
+                    Reference::Tag(tag) => event["tag"] = tag.as_str().into(),
+                    Reference::Digest(digest) => event["digest"] = digest.as_str().into(),
+                }
+
+                event
+            })
+            .collect::<Vec<_>>();
+
+        events.push(json!({
+            "event": "pull_summary",
+            "period_start": start,
+            "period_end": end,
+            "images": self.images.len(),
+            "pulls": self.images.iter().map(|(_, _, count)| count.pulls).sum::<u64>(),
+            "checks": self.images.iter().map(|(_, _, count)| count.checks).sum::<u64>(),
+            "dropped": self.dropped,
+        }));
+
+        events
+    }
+
+    /// Write the events of the summary to standard output, unless there is nothing to summarize.
+    pub fn write(&self) {
+        if !self.is_empty() {
+            for event in self.events() {
+                println!("{event}");
+            }
+        }
+    }
+}
+
+/// Counts of manifest pulls by repository and reference, aggregated across the invocations of a
+/// warm execution environment until they are [taken](PullAnalytics::take).
+#[derive(Debug)]
+pub struct PullAnalytics {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    start: SystemTime,
+    counts: HashMap<(String, Reference), PullCount>,
+    dropped: u64,
+}
+
+impl Default for PullAnalytics {
+    fn default() -> Self {
+        Self::new(SystemTime::now())
+    }
+}
+
+impl PullAnalytics {
+    /// Start counting as of the given time.
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            state: Mutex::new(State {
+                start,
+                counts: HashMap::new(),
+                dropped: 0,
+            }),
+        }
+    }
+
+    /// Count a redirected request if it is a `GET` or `HEAD` request for a manifest.
+    pub fn record(&self, method: &Method, endpoint: &Endpoint) {
+        let Endpoint::Manifest {
+            repository,
+            reference,
+        } = endpoint
+        else {
+            return;
+        };
+
+        if *method != Method::GET && *method != Method::HEAD {
+            return;
+        }
+
+        let mut state = self.state.lock();
+        let key = (repository.clone(), reference.clone());
+
+        if !state.counts.contains_key(&key) && state.counts.len() >= PULL_ANALYTICS_MAX_IMAGES {
+            state.dropped += 1;
+            return;
+        }
+
+        let count = state.counts.entry(key).or_default();
+
+        if *method == Method::GET {
+            count.pulls += 1;
+        } else {
+            count.checks += 1;
+        }
+    }
+
+    /// Take the counts since the previous summary, starting a new period at the given time.
+    pub fn take(&self, now: SystemTime) -> PullSummary {
+        self.state.lock().take(now)
+    }
+
+    /// Take the counts if the interval has passed since the previous summary.
+    pub fn take_if_due(&self, interval: Duration, now: SystemTime) -> Option<PullSummary> {
+        let mut state = self.state.lock();
+
+        let due = state
+            .start
+            .checked_add(interval)
+            .is_some_and(|due| now >= due);
+
+        due.then(|| state.take(now))
+    }
+}
+
+impl State {
+    fn take(&mut self, now: SystemTime) -> PullSummary {
+        let start = std::mem::replace(&mut self.start, now);
+
+        let mut images = self
+            .counts
+            .drain()
+            .map(|((repository, reference), count)| (repository, reference, count))
+            .collect::<Vec<_>>();
+
+        images
+            .sort_by(|(r1, ref1, _), (r2, ref2, _)| (r1, ref1.as_str()).cmp(&(r2, ref2.as_str())));
+
+        PullSummary {
+            start,
+            end: now,
+            images,
+            dropped: std::mem::take(&mut self.dropped),
+        }
+    }
+}
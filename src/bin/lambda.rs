@@ -1,21 +1,56 @@
 use lambda_runtime::{Error, LambdaEvent, service_fn};
 use std::env;
+use tokio::signal::unix::{SignalKind, signal};
 
+use lambda_ecr_rewrite::extension::{RUNTIME_API_ENV_VAR, register_shutdown_extension};
+use lambda_ecr_rewrite::logging::Logger;
 use lambda_ecr_rewrite::responses::ApiGatewayResponseType;
-use lambda_ecr_rewrite::rewrite;
+use lambda_ecr_rewrite::rewriter::{Rewriter, install_panic_hook};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    if let Some(s) = env::args().nth(1) {
-        if s == "test" {
-            eprintln!("Startup test passed.");
-            return Ok(());
-        }
+    // log panics as JSON lines like everything else
+    install_panic_hook();
+
+    let rewriter = Rewriter::from_env().inspect_err(|e| Logger::default().error(e.to_string()))?;
+
+    if let Some(s) = env::args().nth(1)
+        && s == "test"
+    {
+        rewriter.config().logger().info("Startup test passed.");
+        return Ok(());
+    }
+
+    // lambda only signals shutdown when an extension is registered, so register one which does
+    // nothing else
+    let mut sigterm = signal(SignalKind::terminate())?;
+
+    if env::var_os(RUNTIME_API_ENV_VAR).is_some()
+        && let Err(e) = register_shutdown_extension().await
+    {
+        rewriter.config().logger().warn(format!(
+            "Unable to register an extension, so buffered analytics and spans are lost at shutdown: {e}"
+        ));
     }
 
-    lambda_runtime::run(service_fn(handler)).await
+    let handler_rewriter = &rewriter;
+
+    tokio::select! {
+        result = lambda_runtime::run(service_fn(move |event| handler(event, handler_rewriter))) => {
+            result
+        }
+        _ = sigterm.recv() => {
+            // return rather than exit, so that everything written is flushed
+            rewriter.flush_analytics();
+            rewriter.flush_spans();
+            Ok(())
+        }
+    }
 }
 
-async fn handler(event: LambdaEvent<serde_json::Value>) -> Result<ApiGatewayResponseType, Error> {
-    Ok(rewrite(event.payload, event.context))
+async fn handler(
+    event: LambdaEvent<serde_json::Value>,
+    rewriter: &Rewriter,
+) -> Result<ApiGatewayResponseType, Error> {
+    Ok(rewriter.rewrite(event.payload, event.context))
 }
@@ -0,0 +1,152 @@
+use crate::registry::{Endpoint, EndpointKind};
+use bon::Builder;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// The `max-age` in seconds of redirects for content-addressed (digest) references by default:
+/// one year.
+pub const IMMUTABLE_MAX_AGE_DEFAULT: usize = 365 * 24 * 60 * 60;
+
+/// The directives of a `Cache-Control` response header.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheDirectives {
+    #[builder(default)]
+    pub public: bool,
+    #[builder(default)]
+    pub no_store: bool,
+    #[builder(default)]
+    pub no_cache: bool,
+    pub max_age: Option<usize>,
+    /// The max age for shared caches such as CloudFront, overriding `max-age` for them.
+    pub s_maxage: Option<usize>,
+    pub stale_while_revalidate: Option<usize>,
+    pub stale_if_error: Option<usize>,
+    #[builder(default)]
+    pub immutable: bool,
+}
+
+impl CacheDirectives {
+    /// Only `max-age`.
+    pub fn max_age(max_age: usize) -> Self {
+        Self::builder().max_age(max_age).build()
+    }
+
+    /// Only `no-store`.
+    pub fn no_store() -> Self {
+        Self::builder().no_store(true).build()
+    }
+
+    /// The directives with durations in seconds, for validation.
+    pub(crate) fn durations(&self) -> impl Iterator<Item = (&'static str, usize)> {
+        [
+            ("max_age", self.max_age),
+            ("s_maxage", self.s_maxage),
+            ("stale_while_revalidate", self.stale_while_revalidate),
+            ("stale_if_error", self.stale_if_error),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|v| (name, v)))
+    }
+}
+
+impl Display for CacheDirectives {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut directives = vec![];
+
+        if self.public {
+            directives.push("public".to_string());
+        }
+
+        if self.no_store {
+            directives.push("no-store".into());
+        }
+
+        if self.no_cache {
+            directives.push("no-cache".into());
+        }
+
+        if let Some(v) = self.max_age {
+            directives.push(format!("max-age={v}"));
+        }
+
+        if let Some(v) = self.s_maxage {
+            directives.push(format!("s-maxage={v}"));
+        }
+
+        if let Some(v) = self.stale_while_revalidate {
+            directives.push(format!("stale-while-revalidate={v}"));
+        }
+
+        if let Some(v) = self.stale_if_error {
+            directives.push(format!("stale-if-error={v}"));
+        }
+
+        if self.immutable {
+            directives.push("immutable".into());
+        }
+
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+/// `Cache-Control` directives for redirects, by the kind of endpoint redirected to.
+///
+/// Unset policies fall back to defaults: digest-addressed manifests and blobs are cached for a
+/// year as `immutable`, listings (`_catalog` and `tags/list`) are not stored, and everything else,
+/// including tag-addressed manifests, uses the `max-age` of [Config::cache_max_age].
+///
+/// [Config::cache_max_age]: crate::config::Config::cache_max_age
+#[derive(Debug, Clone, Default, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CachePolicy {
+    /// Manifests and blobs addressed by digest.
+    pub digest: Option<CacheDirectives>,
+    /// Manifests addressed by tag.
+    pub tag: Option<CacheDirectives>,
+    /// The repository catalog and tag lists.
+    pub listing: Option<CacheDirectives>,
+    /// All other endpoints.
+    pub default: Option<CacheDirectives>,
+}
+
+impl CachePolicy {
+    /// The directives to send with a redirect to the given endpoint.
+    pub fn directives(&self, endpoint: &Endpoint, max_age: usize) -> CacheDirectives {
+        let default = || {
+            self.default
+                .clone()
+                .unwrap_or_else(|| CacheDirectives::max_age(max_age))
+        };
+
+        if endpoint.is_content_addressed() {
+            return self.digest.clone().unwrap_or_else(|| {
+                CacheDirectives::builder()
+                    .max_age(IMMUTABLE_MAX_AGE_DEFAULT)
+                    .immutable(true)
+                    .build()
+            });
+        }
+
+        match endpoint.kind() {
+            EndpointKind::Manifest => self.tag.clone().unwrap_or_else(default),
+            EndpointKind::Catalog | EndpointKind::Tags => self
+                .listing
+                .clone()
+                .unwrap_or_else(CacheDirectives::no_store),
+            _ => default(),
+        }
+    }
+
+    /// All configured directives, by name, for validation.
+    pub(crate) fn configured(&self) -> impl Iterator<Item = (&'static str, &CacheDirectives)> {
+        [
+            ("digest", &self.digest),
+            ("tag", &self.tag),
+            ("listing", &self.listing),
+            ("default", &self.default),
+        ]
+        .into_iter()
+        .filter_map(|(name, directives)| directives.as_ref().map(|d| (name, d)))
+    }
+}
@@ -0,0 +1,947 @@
+use crate::access::AccessLogFormat;
+use crate::analytics::AnalyticsConfig;
+use crate::cache::CachePolicy;
+use crate::ecr::{self, is_ecr_registry_host};
+use crate::escape_json;
+use crate::logging::{DEBUG_HEADER, Level, LogLimit, Logger, SampleRate, constant_time_eq};
+use crate::methods::MethodPolicy;
+use crate::metrics::MetricsConfig;
+use crate::redaction::Redaction;
+use crate::repositories::RepositoryPolicy;
+use crate::requests::ApiGatewayRequestType;
+use crate::telemetry::{OTLP_TRACES_PATH, TracingConfig};
+use crate::templates::{ErrorPages, TemplateValues, is_valid_support_link};
+use bon::Builder;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The name of the environment variable containing the path to a TOML, JSON, or YAML
+/// configuration file.
+pub const CONFIG_FILE_ENV_VAR: &str = "CONFIG_FILE";
+
+/// The name of the environment variable containing the ECR registry host FQDN.
+pub const ECR_REGISTRY_ENV_VAR: &str = "ECR_REGISTRY_HOST";
+
+/// The name of the environment variable containing the AWS account ID of the ECR registry, used
+/// to derive the registry host if [ECR_REGISTRY_ENV_VAR] is not set.
+pub const ECR_ACCOUNT_ID_ENV_VAR: &str = "ECR_ACCOUNT_ID";
+
+/// The name of the environment variable containing the region of the ECR registry, used to derive
+/// the registry host. Defaults to the value of [AWS_REGION_ENV_VAR].
+pub const ECR_REGION_ENV_VAR: &str = "ECR_REGION";
+
+/// The name of the environment variable, set by Lambda, containing the region of the function.
+pub const AWS_REGION_ENV_VAR: &str = "AWS_REGION";
+
+/// The name of the environment variable which, when set to `y | yes | true`, selects the FIPS
+/// endpoint of the ECR registry when deriving its host.
+pub const ECR_FIPS_ENV_VAR: &str = "ECR_FIPS";
+
+/// The name of the environment variable which, when set to `y | yes | true`, selects the
+/// dual-stack (IPv4 and IPv6) endpoint of the ECR registry when deriving its host.
+pub const ECR_DUAL_STACK_ENV_VAR: &str = "ECR_DUAL_STACK";
+
+/// The name of the environment variable containing the cache max age in seconds to return with
+/// responses.
+pub const CACHE_MAX_AGE_ENV_VAR: &str = "CACHE_MAX_AGE";
+
+/// The name of the environment variable containing the HTTP methods to redirect, either as a
+/// preset (`read-only`, `read-write`) or a comma-separated list of methods.
+pub const ALLOWED_METHODS_ENV_VAR: &str = "ALLOWED_METHODS";
+
+/// The name of the environment variable containing a comma-separated list of the repositories to
+/// redirect, as globs or regexes, optionally with a tag pattern; see
+/// [RepositoryRule](crate::repositories::RepositoryRule).
+pub const ALLOWED_REPOSITORIES_ENV_VAR: &str = "ALLOWED_REPOSITORIES";
+
+/// The name of the environment variable containing a comma-separated list of the repositories to
+/// deny, in the same form as [ALLOWED_REPOSITORIES_ENV_VAR].
+pub const DENIED_REPOSITORIES_ENV_VAR: &str = "DENIED_REPOSITORIES";
+
+/// The name of the environment variable containing how requests for denied repositories are
+/// answered: `denied` (403) or `name-unknown` (404).
+pub const DENIED_RESPONSE_ENV_VAR: &str = "DENIED_REPOSITORY_RESPONSE";
+
+/// The name of the environment variable containing the base path to remove from request paths,
+/// for use with custom domain base path mappings.
+pub const BASE_PATH_ENV_VAR: &str = "BASE_PATH";
+
+/// The name of the environment variable which enables debug logging when set to `y | yes | true`.
+pub const DEBUG_ENV_VAR: &str = "DEBUG";
+
+/// The name of the environment variable containing the fraction of requests, between `0.0` and
+/// `1.0`, for which to enable debug logging.
+pub const DEBUG_SAMPLE_RATE_ENV_VAR: &str = "DEBUG_SAMPLE_RATE";
+
+/// The name of the environment variable containing the secret which enables trace logging of a
+/// request when sent in the [DEBUG_HEADER] header.
+pub const DEBUG_SECRET_ENV_VAR: &str = "DEBUG_SECRET";
+
+/// The shortest debug secret considered valid.
+pub const DEBUG_SECRET_MIN_LEN: usize = 16;
+
+/// The name of the environment variable containing the least severe level to log: `error`,
+/// `warn`, `info`, `debug`, or `trace`.
+pub const LOG_LEVEL_ENV_VAR: &str = "LOG_LEVEL";
+
+/// The name of the environment variable containing the budget of repeated error log lines as
+/// `<burst>/<interval seconds>`, such as `5/60`.
+pub const LOG_RATE_LIMIT_ENV_VAR: &str = "LOG_RATE_LIMIT";
+
+/// The name of the environment variable containing a comma-separated list of headers to redact
+/// from logged payloads, replacing the defaults.
+pub const REDACT_HEADERS_ENV_VAR: &str = "REDACT_HEADERS";
+
+/// The name of the environment variable containing a comma-separated list of JSON paths to redact
+/// from logged payloads, replacing the defaults.
+pub const REDACT_PATHS_ENV_VAR: &str = "REDACT_PATHS";
+
+/// The name of the environment variable which, when set to `y | yes | true`, enables writing
+/// metrics in the CloudWatch Embedded Metric Format to standard output.
+pub const METRICS_ENV_VAR: &str = "METRICS";
+
+/// The name of the environment variable containing the CloudWatch namespace of metrics.
+pub const METRICS_NAMESPACE_ENV_VAR: &str = "METRICS_NAMESPACE";
+
+/// The name of the environment variable containing the format of the access log: `off`, `common`,
+/// `combined`, or `json`.
+pub const ACCESS_LOG_ENV_VAR: &str = "ACCESS_LOG";
+
+/// The name of the environment variable containing the number of proxies in front of API Gateway,
+/// such as CloudFront, whose `X-Forwarded-For` entries are trusted to find the client's address.
+pub const TRUSTED_PROXIES_ENV_VAR: &str = "TRUSTED_PROXIES";
+
+/// The name of the environment variable which, when set to `y | yes | true`, enables counting
+/// manifest pulls by repository and reference and periodically writing summaries of them.
+pub const PULL_ANALYTICS_ENV_VAR: &str = "PULL_ANALYTICS";
+
+/// The name of the environment variable containing the interval in seconds at which to write
+/// summaries of pulls.
+pub const PULL_ANALYTICS_INTERVAL_ENV_VAR: &str = "PULL_ANALYTICS_INTERVAL";
+
+/// The name of the environment variable containing the base URL of an OTLP/HTTP endpoint, to which
+/// [OTLP_TRACES_PATH] is appended to export spans.
+pub const OTEL_ENDPOINT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// The name of the environment variable containing the URL of an OTLP/HTTP traces endpoint, used
+/// as-is and taking precedence over [OTEL_ENDPOINT_ENV_VAR].
+pub const OTEL_TRACES_ENDPOINT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT";
+
+/// The name of the environment variable containing the timeout in milliseconds of exporting spans.
+pub const OTEL_TIMEOUT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_TIMEOUT";
+
+/// The name of the environment variable containing the `service.name` of exported spans.
+pub const OTEL_SERVICE_NAME_ENV_VAR: &str = "OTEL_SERVICE_NAME";
+
+/// The name of the environment variable which enables strict configuration validation at startup
+/// when set to `y | yes | true`.
+pub const STRICT_CONFIG_ENV_VAR: &str = "STRICT_CONFIG";
+
+/// The name of the environment variable containing the interval in seconds at which to reload
+/// the configuration file named by [CONFIG_FILE_ENV_VAR]. If unset, the file is read only once.
+pub const CONFIG_RELOAD_TTL_ENV_VAR: &str = "CONFIG_RELOAD_TTL";
+
+/// The name of the environment variable which, when set to `y | yes | true`, enables overriding
+/// configuration per request with API Gateway stage variables of the same names as the
+/// environment variables listed in [STAGE_VARIABLE_OVERRIDES].
+pub const STAGE_VARIABLES_ENV_VAR: &str = "STAGE_VARIABLES";
+
+/// The environment variables which stage variables may override: those deciding where requests
+/// are routed, but none affecting logging, redaction, or how the configuration is validated.
+pub const STAGE_VARIABLE_OVERRIDES: &[&str] = &[
+    ECR_REGISTRY_ENV_VAR,
+    ECR_ACCOUNT_ID_ENV_VAR,
+    ECR_REGION_ENV_VAR,
+    BASE_PATH_ENV_VAR,
+    CACHE_MAX_AGE_ENV_VAR,
+    ALLOWED_REPOSITORIES_ENV_VAR,
+    DENIED_REPOSITORIES_ENV_VAR,
+    DENIED_RESPONSE_ENV_VAR,
+];
+
+/// The most stage configurations [StageConfigs] keeps, bounding its memory.
+pub const STAGE_CONFIGS_MAX: usize = 64;
+
+/// The default max age in seconds to specify in the `Cache-Control` header.
+pub const CACHE_MAX_AGE_DEFAULT: usize = 60;
+
+/// The largest max age in seconds considered valid: one year.
+pub const CACHE_MAX_AGE_MAX: usize = 365 * 24 * 60 * 60;
+
+/// Configuration for rewriting requests.
+///
+/// Usually loaded with [Config::from_env], which reads the file named by [CONFIG_FILE_ENV_VAR]
+/// (if any) and then applies environment variable overrides.
+#[derive(Debug, Clone, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The FQDN of the ECR registry to redirect to. If unset, it is derived from the account ID and
+    /// region; see [Config::resolved_registry_host].
+    #[builder(into)]
+    pub registry_host: Option<String>,
+    /// The AWS account ID of the ECR registry.
+    #[builder(into)]
+    pub account_id: Option<String>,
+    /// The region of the ECR registry.
+    #[builder(into)]
+    pub region: Option<String>,
+    /// Whether to use the FIPS endpoint of the ECR registry.
+    #[builder(default)]
+    pub fips: bool,
+    /// Whether to use the dual-stack (IPv4 and IPv6) endpoint of the ECR registry.
+    #[builder(default)]
+    pub dual_stack: bool,
+    /// The `max-age` in seconds to send in the `Cache-Control` header of redirects, unless
+    /// overridden for the endpoint by [cache](Config::cache).
+    #[builder(default = CACHE_MAX_AGE_DEFAULT)]
+    pub cache_max_age: usize,
+    /// The `Cache-Control` directives of redirects by endpoint.
+    #[builder(default)]
+    pub cache: CachePolicy,
+    /// Templates and branding of error responses, by vanity domain.
+    #[builder(default)]
+    pub error_pages: ErrorPages,
+    /// The HTTP methods to redirect; all others receive a 405.
+    #[builder(default)]
+    pub allowed_methods: MethodPolicy,
+    /// The repositories to redirect; requests for all others receive a 403 or 404.
+    #[builder(default)]
+    pub repositories: RepositoryPolicy,
+    /// The base path to remove from request paths.
+    #[builder(into)]
+    pub base_path: Option<String>,
+    /// Whether to log request and response payloads, regardless of [log_level](Config::log_level).
+    #[builder(default)]
+    pub debug: bool,
+    /// The fraction of requests for which to log payloads, as if [debug](Config::debug) were
+    /// enabled.
+    #[builder(default)]
+    pub debug_sample_rate: SampleRate,
+    /// The secret which, sent in the [DEBUG_HEADER] header, enables trace logging of a request.
+    #[builder(into)]
+    #[serde(skip_serializing)]
+    pub debug_secret: Option<String>,
+    /// The least severe level to log.
+    #[builder(default)]
+    pub log_level: Level,
+    /// How often errors with the same cause may be logged.
+    #[builder(default)]
+    pub log_limit: LogLimit,
+    /// What to mask in payloads before they are logged.
+    #[builder(default)]
+    pub redaction: Redaction,
+    /// Whether and where to publish metrics.
+    #[builder(default)]
+    pub metrics: MetricsConfig,
+    /// The format of the access log, if any.
+    #[builder(default)]
+    pub access_log: AccessLogFormat,
+    /// The number of proxies in front of API Gateway which append to `X-Forwarded-For`.
+    #[builder(default)]
+    pub trusted_proxies: usize,
+    /// Whether and how often to summarize pulls.
+    #[builder(default)]
+    pub analytics: AnalyticsConfig,
+    /// Whether and where to export OpenTelemetry spans.
+    #[builder(default)]
+    pub tracing: TracingConfig,
+    /// Whether invalid configuration should fail startup rather than being logged and ignored.
+    #[builder(default)]
+    pub strict: bool,
+    /// Whether API Gateway stage variables override this configuration for each request.
+    #[builder(default)]
+    pub stage_variables: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Config {
+    /// Load the configuration file named by [CONFIG_FILE_ENV_VAR], if set, and apply overrides from
+    /// the process environment.
+    ///
+    /// Overrides with invalid values and failed [validation](Config::validate) are logged as
+    /// warnings, unless [strict](Config::strict) mode is enabled, in which case they are returned
+    /// as an error. Invalid repository rules are always an error, as ignoring them would redirect
+    /// requests for repositories they are meant to deny.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::load(|name| env::var(name).ok())
+    }
+
+    /// Load configuration as [Config::from_env] does, looking up environment variables with the
+    /// given function.
+    pub fn load(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let (config, mut errors) = match lookup(CONFIG_FILE_ENV_VAR) {
+            Some(path) => Self::from_file(path)?.checked(&lookup)?,
+            None => Self::default().checked(&lookup)?,
+        };
+
+        // there is nothing to reload without a file
+        if lookup(CONFIG_FILE_ENV_VAR).is_none() && lookup(CONFIG_RELOAD_TTL_ENV_VAR).is_some() {
+            let error = ConfigError::invalid(
+                CONFIG_RELOAD_TTL_ENV_VAR,
+                format!("{CONFIG_FILE_ENV_VAR} must be set for the configuration to be reloaded"),
+            );
+
+            if config.strict {
+                return Err(ConfigError::Multiple(vec![error]));
+            }
+
+            errors.push(error);
+        }
+
+        config.warn(&errors);
+
+        Ok(config)
+    }
+
+    /// Apply environment variable overrides, looked up with the given function, and validate the
+    /// result, failing only in [strict](Config::strict) mode or if repository rules are invalid.
+    pub fn finish(self, lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let (config, errors) = self.checked(lookup)?;
+        config.warn(&errors);
+
+        Ok(config)
+    }
+
+    /// Apply environment variable overrides and validate the result as [Config::finish] does,
+    /// returning the errors to be warned about rather than logging them.
+    pub fn checked(
+        mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<(Self, Vec<ConfigError>), ConfigError> {
+        let mut errors = self.apply_overrides(lookup);
+        errors.extend(self.validate());
+
+        if (self.strict || errors.iter().any(ConfigError::fails_open)) && !errors.is_empty() {
+            return Err(ConfigError::Multiple(errors));
+        }
+
+        Ok((self, errors))
+    }
+
+    /// Log invalid configuration which is being ignored.
+    pub(crate) fn warn(&self, errors: &[ConfigError]) {
+        for e in errors {
+            self.logger().warn(format!("Invalid configuration: {e}"));
+        }
+    }
+
+    /// The configuration to use for a request: if [stage_variables](Config::stage_variables) is
+    /// enabled, the request's stage variables named in [STAGE_VARIABLE_OVERRIDES] are applied as
+    /// overrides, using the same names as the environment variables, e.g. `ECR_REGISTRY_HOST`.
+    ///
+    /// Invalid stage variables are ignored and returned for the caller to log. If the resulting
+    /// configuration fails [validation](Config::validate) where this one does not, such as with an
+    /// `ECR_REGISTRY_HOST` which is not an ECR registry, or if any repository rule is invalid, the
+    /// stage variables are ignored altogether and this configuration is used.
+    pub fn for_request(&self, req: &ApiGatewayRequestType) -> (Cow<'_, Self>, Vec<ConfigError>) {
+        let vars = self.stage_overrides(req);
+
+        if vars.is_empty() {
+            return (Cow::Borrowed(self), vec![]);
+        }
+
+        let (config, errors) = self.with_stage_overrides(&vars);
+        (Cow::Owned(config), errors)
+    }
+
+    /// The stage variables of a request which override this configuration, in the order of
+    /// [STAGE_VARIABLE_OVERRIDES].
+    fn stage_overrides(&self, req: &ApiGatewayRequestType) -> Vec<(&'static str, String)> {
+        if !self.stage_variables {
+            return vec![];
+        }
+
+        let vars = req.stage_variables();
+
+        STAGE_VARIABLE_OVERRIDES
+            .iter()
+            .filter_map(|&name| vars.get(name).map(|v| (name, v.clone())))
+            .collect()
+    }
+
+    fn with_stage_overrides(&self, vars: &[(&'static str, String)]) -> (Self, Vec<ConfigError>) {
+        let mut config = self.clone();
+
+        let mut errors = config.apply_overrides(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, v)| v.clone())
+        });
+
+        // only problems introduced by the stage variables disqualify them
+        let base_errors = self
+            .validate()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        let len = errors.len();
+        let fails_open = errors.iter().any(ConfigError::fails_open);
+        errors.extend(
+            config
+                .validate()
+                .into_iter()
+                .filter(|e| !base_errors.contains(&e.to_string())),
+        );
+
+        if fails_open || errors.len() > len {
+            return (self.clone(), errors);
+        }
+
+        (config, errors)
+    }
+
+    /// The logger at the configured level, which is at least [Level::Debug] if
+    /// [debug](Config::debug) is enabled.
+    pub fn logger(&self) -> Logger {
+        Logger::new(if self.debug {
+            self.log_level.max(Level::Debug)
+        } else {
+            self.log_level
+        })
+    }
+
+    /// The logger for a request: as [Config::logger], but at least at [Level::Debug] if the request
+    /// is sampled at the [debug_sample_rate](Config::debug_sample_rate), and at [Level::Trace] if it
+    /// carries the [debug_secret](Config::debug_secret) in the [DEBUG_HEADER] header.
+    pub fn request_logger(&self, req: &ApiGatewayRequestType) -> Logger {
+        let logger = self.logger();
+
+        let forced = self
+            .debug_secret
+            .as_deref()
+            .filter(|secret| !secret.is_empty())
+            .is_some_and(|secret| {
+                req.headers()
+                    .get(DEBUG_HEADER)
+                    .is_some_and(|value| constant_time_eq(value.as_bytes(), secret.as_bytes()))
+            });
+
+        if forced {
+            Logger::new(Level::Trace)
+        } else if !logger.enabled(Level::Debug) && self.debug_sample_rate.sample() {
+            Logger::new(Level::Debug)
+        } else {
+            logger
+        }
+    }
+
+    /// The host of the registry to redirect to: either [registry_host](Config::registry_host), or
+    /// if unset, the host derived from the account ID, region, and endpoint options.
+    pub fn resolved_registry_host(&self) -> Result<Cow<'_, str>, ConfigError> {
+        if let Some(host) = self.registry_host.as_deref() {
+            return Ok(Cow::Borrowed(host));
+        }
+
+        let Some(account_id) = self.account_id.as_deref() else {
+            return Err(ConfigError::invalid(
+                ECR_REGISTRY_ENV_VAR,
+                format!(
+                    "no registry host is configured; set either {ECR_REGISTRY_ENV_VAR} or {ECR_ACCOUNT_ID_ENV_VAR}"
+                ),
+            ));
+        };
+
+        let Some(region) = self.region.as_deref() else {
+            return Err(ConfigError::invalid(
+                ECR_REGION_ENV_VAR,
+                "no region is configured to derive the registry host",
+            ));
+        };
+
+        ecr::registry_host(account_id, region, self.fips, self.dual_stack)
+            .map(Cow::Owned)
+            .map_err(|e| ConfigError::invalid(ECR_ACCOUNT_ID_ENV_VAR, e))
+    }
+
+    /// Check the configuration for missing or invalid values, returning every problem found.
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = vec![];
+
+        match self.resolved_registry_host() {
+            Err(e) => errors.push(e),
+            Ok(host) if !is_valid_hostname(&host) => errors.push(ConfigError::invalid(
+                ECR_REGISTRY_ENV_VAR,
+                format!("{host:?} is not a valid host name"),
+            )),
+            Ok(host) if !is_ecr_registry_host(&host) => errors.push(ConfigError::invalid(
+                ECR_REGISTRY_ENV_VAR,
+                format!("{host:?} is not an ECR registry host name"),
+            )),
+            _ => {}
+        }
+
+        if self.cache_max_age > CACHE_MAX_AGE_MAX {
+            errors.push(ConfigError::invalid(
+                CACHE_MAX_AGE_ENV_VAR,
+                format!(
+                    "{} exceeds the maximum of {CACHE_MAX_AGE_MAX} seconds",
+                    self.cache_max_age
+                ),
+            ));
+        }
+
+        for (name, directives) in self.cache.configured() {
+            for (directive, value) in directives.durations() {
+                if value > CACHE_MAX_AGE_MAX {
+                    errors.push(ConfigError::invalid(
+                        format!("cache.{name}.{directive}"),
+                        format!("{value} exceeds the maximum of {CACHE_MAX_AGE_MAX} seconds"),
+                    ));
+                }
+            }
+        }
+
+        if let Some(secret) = &self.debug_secret
+            && secret.len() < DEBUG_SECRET_MIN_LEN
+        {
+            errors.push(ConfigError::invalid(
+                DEBUG_SECRET_ENV_VAR,
+                format!("the secret must be at least {DEBUG_SECRET_MIN_LEN} characters long"),
+            ));
+        }
+
+        if self.log_limit.burst == 0 || self.log_limit.interval == 0 {
+            errors.push(ConfigError::invalid(
+                LOG_RATE_LIMIT_ENV_VAR,
+                "the burst and interval must both be positive",
+            ));
+        }
+
+        if self.metrics.namespace.is_empty() || self.metrics.namespace.len() > 255 {
+            errors.push(ConfigError::invalid(
+                METRICS_NAMESPACE_ENV_VAR,
+                "the namespace must be between 1 and 255 characters",
+            ));
+        }
+
+        if let Some(endpoint) = self.tracing.invalid_endpoint() {
+            errors.push(ConfigError::invalid(
+                OTEL_TRACES_ENDPOINT_ENV_VAR,
+                format!("{endpoint:?} is not an http:// or https:// URL"),
+            ));
+        }
+
+        if self.tracing.service_name.is_empty() {
+            errors.push(ConfigError::invalid(
+                OTEL_SERVICE_NAME_ENV_VAR,
+                "the service name must not be empty",
+            ));
+        }
+
+        for path in self.redaction.invalid_paths() {
+            errors.push(ConfigError::invalid(
+                REDACT_PATHS_ENV_VAR,
+                format!("{path:?} contains an empty segment"),
+            ));
+        }
+
+        for (key, branding) in self.error_pages.configured() {
+            if let Some(template) = &branding.json {
+                let rendered = template.render(&TemplateValues::sample(), escape_json);
+
+                if let Err(e) = serde_json::from_str::<serde_json::Value>(&rendered) {
+                    errors.push(ConfigError::invalid(
+                        format!("{key}.json"),
+                        format!("the template does not render valid JSON: {e}"),
+                    ));
+                }
+            }
+
+            if let Some(link) = &branding.support_link
+                && !is_valid_support_link(link)
+            {
+                errors.push(ConfigError::invalid(
+                    format!("{key}.support_link"),
+                    format!("{link:?} is not an http(s) or mailto URL, so it is not rendered"),
+                ));
+            }
+        }
+
+        errors
+    }
+
+    /// Load configuration from a file, determining its format from the extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => return Err(ConfigError::UnsupportedFormat(path.to_path_buf())),
+        };
+
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+
+        Self::parse(&contents, format).map_err(|e| match e {
+            ConfigError::Parse(_, message) => ConfigError::Parse(Some(path.to_path_buf()), message),
+            e => e,
+        })
+    }
+
+    /// Parse configuration from a string in the given format.
+    pub fn parse(contents: &str, format: ConfigFormat) -> Result<Self, ConfigError> {
+        match format {
+            ConfigFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        }
+        .map_err(|message| ConfigError::Parse(None, message))
+    }
+
+    /// Apply overrides from environment variables, looked up with the given function.
+    ///
+    /// Valid overrides are applied and invalid ones are skipped and returned.
+    pub fn apply_overrides(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Vec<ConfigError> {
+        let mut errors = vec![];
+
+        if let Some(v) = lookup(ECR_REGISTRY_ENV_VAR) {
+            self.registry_host = Some(v);
+        }
+
+        if let Some(v) = lookup(ECR_ACCOUNT_ID_ENV_VAR) {
+            self.account_id = Some(v.trim().to_string());
+        }
+
+        // the function's own region only applies if no region was configured at all
+        if let Some(v) = lookup(ECR_REGION_ENV_VAR) {
+            self.region = Some(v.trim().to_string());
+        } else if self.region.is_none()
+            && let Some(v) = lookup(AWS_REGION_ENV_VAR)
+        {
+            self.region = Some(v.trim().to_string());
+        }
+
+        if let Some(v) = lookup(ECR_FIPS_ENV_VAR) {
+            self.fips = is_truthy(&v);
+        }
+
+        if let Some(v) = lookup(ECR_DUAL_STACK_ENV_VAR) {
+            self.dual_stack = is_truthy(&v);
+        }
+
+        if let Some(v) = lookup(CACHE_MAX_AGE_ENV_VAR) {
+            match v.trim().parse() {
+                Ok(max_age) => self.cache_max_age = max_age,
+                Err(e) => errors.push(ConfigError::invalid(CACHE_MAX_AGE_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(ALLOWED_METHODS_ENV_VAR) {
+            match v.parse() {
+                Ok(policy) => self.allowed_methods = policy,
+                Err(e) => errors.push(ConfigError::invalid(ALLOWED_METHODS_ENV_VAR, e)),
+            }
+        }
+
+        for (key, rules) in [
+            (ALLOWED_REPOSITORIES_ENV_VAR, &mut self.repositories.allow),
+            (DENIED_REPOSITORIES_ENV_VAR, &mut self.repositories.deny),
+        ] {
+            if let Some(v) = lookup(key) {
+                match split_list(&v).iter().map(|rule| rule.parse()).collect() {
+                    Ok(parsed) => *rules = parsed,
+                    Err(e) => errors.push(ConfigError::invalid(key, e)),
+                }
+            }
+        }
+
+        if let Some(v) = lookup(DENIED_RESPONSE_ENV_VAR) {
+            match v.parse() {
+                Ok(response) => self.repositories.denied_response = response,
+                Err(e) => errors.push(ConfigError::invalid(DENIED_RESPONSE_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(BASE_PATH_ENV_VAR) {
+            self.base_path = Some(v);
+        }
+
+        if let Some(v) = lookup(DEBUG_ENV_VAR) {
+            self.debug = is_truthy(&v);
+        }
+
+        if let Some(v) = lookup(DEBUG_SAMPLE_RATE_ENV_VAR) {
+            match v.parse() {
+                Ok(rate) => self.debug_sample_rate = rate,
+                Err(e) => errors.push(ConfigError::invalid(DEBUG_SAMPLE_RATE_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(DEBUG_SECRET_ENV_VAR) {
+            self.debug_secret = Some(v.trim().to_string()).filter(|secret| !secret.is_empty());
+        }
+
+        if let Some(v) = lookup(LOG_LEVEL_ENV_VAR) {
+            match v.parse() {
+                Ok(level) => self.log_level = level,
+                Err(e) => errors.push(ConfigError::invalid(LOG_LEVEL_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(LOG_RATE_LIMIT_ENV_VAR) {
+            match v.parse() {
+                Ok(limit) => self.log_limit = limit,
+                Err(e) => errors.push(ConfigError::invalid(LOG_RATE_LIMIT_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(REDACT_HEADERS_ENV_VAR) {
+            self.redaction.headers = split_list(&v);
+        }
+
+        if let Some(v) = lookup(REDACT_PATHS_ENV_VAR) {
+            self.redaction.paths = split_list(&v);
+        }
+
+        if let Some(v) = lookup(METRICS_ENV_VAR) {
+            self.metrics.enabled = is_truthy(&v);
+        }
+
+        if let Some(v) = lookup(METRICS_NAMESPACE_ENV_VAR) {
+            self.metrics.namespace = v.trim().to_string();
+        }
+
+        if let Some(v) = lookup(ACCESS_LOG_ENV_VAR) {
+            match v.parse() {
+                Ok(format) => self.access_log = format,
+                Err(e) => errors.push(ConfigError::invalid(ACCESS_LOG_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(TRUSTED_PROXIES_ENV_VAR) {
+            match v.trim().parse() {
+                Ok(count) => self.trusted_proxies = count,
+                Err(e) => errors.push(ConfigError::invalid(TRUSTED_PROXIES_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(PULL_ANALYTICS_ENV_VAR) {
+            self.analytics.enabled = is_truthy(&v);
+        }
+
+        if let Some(v) = lookup(PULL_ANALYTICS_INTERVAL_ENV_VAR) {
+            match v.trim().parse() {
+                Ok(interval) => self.analytics.interval = interval,
+                Err(e) => errors.push(ConfigError::invalid(PULL_ANALYTICS_INTERVAL_ENV_VAR, e)),
+            }
+        }
+
+        let endpoint = |v: &str| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+
+        if let Some(v) = lookup(OTEL_ENDPOINT_ENV_VAR) {
+            self.tracing.endpoint =
+                endpoint(&v).map(|v| format!("{}{OTLP_TRACES_PATH}", v.trim_end_matches('/')));
+        }
+
+        if let Some(v) = lookup(OTEL_TRACES_ENDPOINT_ENV_VAR) {
+            self.tracing.endpoint = endpoint(&v);
+        }
+
+        if let Some(v) = lookup(OTEL_TIMEOUT_ENV_VAR) {
+            match v.trim().parse() {
+                Ok(timeout) => self.tracing.timeout = timeout,
+                Err(e) => errors.push(ConfigError::invalid(OTEL_TIMEOUT_ENV_VAR, e)),
+            }
+        }
+
+        if let Some(v) = lookup(OTEL_SERVICE_NAME_ENV_VAR) {
+            self.tracing.service_name = v.trim().to_string();
+        }
+
+        if let Some(v) = lookup(STRICT_CONFIG_ENV_VAR) {
+            self.strict = is_truthy(&v);
+        }
+
+        if let Some(v) = lookup(STAGE_VARIABLES_ENV_VAR) {
+            self.stage_variables = is_truthy(&v);
+        }
+
+        errors
+    }
+}
+
+/// Whether an environment variable value is one of `y | yes | true`.
+fn is_truthy(value: &str) -> bool {
+    value.trim().starts_with("y") || value.trim() == "true"
+}
+
+/// Split a comma-separated environment variable value into its trimmed, non-empty items.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(Into::into)
+        .collect()
+}
+
+/// Whether the value is a syntactically valid DNS host name, without a scheme, port, or path.
+fn is_valid_hostname(host: &str) -> bool {
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+/// The formats in which a configuration file may be written.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+/// The configurations derived from a base configuration for each set of stage variables, as per
+/// [Config::for_request], so they are derived once per stage rather than on every request.
+///
+/// The cache is emptied whenever the base configuration changes, such as when it is reloaded.
+#[derive(Debug, Default)]
+pub struct StageConfigs {
+    state: Mutex<StageState>,
+}
+
+type StageKey = Vec<(&'static str, String)>;
+
+#[derive(Debug, Default)]
+struct StageState {
+    base: Option<Arc<Config>>,
+    configs: HashMap<StageKey, (Arc<Config>, Arc<[ConfigError]>)>,
+}
+
+impl StageConfigs {
+    /// The configuration to use for a request given the base configuration, along with the
+    /// invalid stage variables which were ignored in deriving it.
+    pub fn get(
+        &self,
+        config: &Arc<Config>,
+        req: &ApiGatewayRequestType,
+    ) -> (Arc<Config>, Arc<[ConfigError]>) {
+        let vars = config.stage_overrides(req);
+
+        if vars.is_empty() {
+            return (config.clone(), Arc::new([]));
+        }
+
+        let mut state = self.state.lock();
+
+        if !state
+            .base
+            .as_ref()
+            .is_some_and(|base| Arc::ptr_eq(base, config))
+        {
+            *state = StageState {
+                base: Some(config.clone()),
+                configs: HashMap::new(),
+            };
+        }
+
+        if let Some(derived) = state.configs.get(&vars) {
+            return derived.clone();
+        }
+
+        let (derived, errors) = config.with_stage_overrides(&vars);
+
+        if state.configs.len() >= STAGE_CONFIGS_MAX {
+            state.configs.clear();
+        }
+
+        // the errors are kept, so that every request with the same stage variables reports them
+        let derived = (Arc::new(derived), Arc::from(errors));
+        state.configs.insert(vars, derived.clone());
+
+        derived
+    }
+}
+
+/// Errors encountered while loading configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Io(PathBuf, std::io::Error),
+    /// The configuration file's extension is not one of the supported formats.
+    UnsupportedFormat(PathBuf),
+    /// The configuration could not be parsed, with the path of the file if there is one.
+    Parse(Option<PathBuf>, String),
+    /// A configuration value is invalid.
+    Invalid { key: String, message: String },
+    /// Several problems were found at once.
+    Multiple(Vec<ConfigError>),
+}
+
+impl ConfigError {
+    pub(crate) fn invalid(key: impl Into<String>, message: impl ToString) -> Self {
+        Self::Invalid {
+            key: key.into(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Whether ignoring this problem could let requests through which the configuration meant to
+    /// reject, as with invalid repository rules, leaving it to fail closed instead.
+    pub fn fails_open(&self) -> bool {
+        match self {
+            Self::Invalid { key, .. } => {
+                key == ALLOWED_REPOSITORIES_ENV_VAR || key == DENIED_REPOSITORIES_ENV_VAR
+            }
+            Self::Multiple(errors) => errors.iter().any(Self::fails_open),
+            _ => false,
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "unable to read {}: {e}", path.display()),
+            Self::UnsupportedFormat(path) => write!(
+                f,
+                "unsupported configuration format for {}; expected .toml, .json, .yaml, or .yml",
+                path.display()
+            ),
+            Self::Parse(Some(path), message) => {
+                write!(f, "unable to parse {}: {message}", path.display())
+            }
+            Self::Parse(None, message) => write!(f, "unable to parse configuration: {message}"),
+            Self::Invalid { key, message } => write!(f, "invalid value for {key}: {message}"),
+            Self::Multiple(errors) => write!(
+                f,
+                "invalid configuration: {}",
+                errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
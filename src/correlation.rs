@@ -0,0 +1,95 @@
+use crate::requests::ApiGatewayRequestType;
+use lambda_runtime::Context;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+/// The response header carrying the Lambda request ID.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// The response header carrying the API Gateway request ID.
+pub const API_REQUEST_ID_HEADER: &str = "X-Api-Request-Id";
+/// The request and response header carrying the X-Ray trace ID.
+pub const TRACE_ID_HEADER: &str = "X-Amzn-Trace-Id";
+
+/// The identifiers which correlate a response or log line with a Lambda invocation, an API
+/// Gateway request, and an X-Ray trace.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct RequestIds {
+    /// The Lambda request ID of the invocation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// The `requestContext.requestId` of the API Gateway event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_request_id: Option<String>,
+    /// The X-Ray trace ID of the invocation, or else of the `X-Amzn-Trace-Id` request header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+impl RequestIds {
+    /// Collect the identifiers of an invocation and, if it could be parsed, its request.
+    pub fn new(ctx: &Context, req: Option<&ApiGatewayRequestType>) -> Self {
+        let from_request = req.map(Self::from_request).unwrap_or_default();
+
+        Self {
+            request_id: Some(ctx.request_id.clone()).filter(|id| !id.is_empty()),
+            api_request_id: from_request.api_request_id,
+            trace_id: ctx
+                .xray_trace_id
+                .clone()
+                .filter(|id| !id.is_empty())
+                .or(from_request.trace_id),
+        }
+    }
+
+    /// Collect the identifiers of a request alone, outside of a Lambda invocation.
+    pub fn from_request(req: &ApiGatewayRequestType) -> Self {
+        Self {
+            request_id: None,
+            api_request_id: req.api_request_id().map(Into::into),
+            trace_id: req
+                .headers()
+                .get(TRACE_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(Into::into),
+        }
+    }
+
+    /// The identifier of the occurrence of an error: the Lambda request ID, or else the API
+    /// Gateway request ID.
+    pub fn primary(&self) -> Option<&str> {
+        self.request_id
+            .as_deref()
+            .or(self.api_request_id.as_deref())
+    }
+
+    /// The response headers carrying the identifiers.
+    pub fn headers(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            (REQUEST_ID_HEADER, self.primary()),
+            (API_REQUEST_ID_HEADER, self.api_request_id.as_deref()),
+            (TRACE_ID_HEADER, self.trace_id.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|v| (name, v)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.request_id.is_none() && self.api_request_id.is_none() && self.trace_id.is_none()
+    }
+}
+
+/// Formats the identifiers as `key=value` pairs for log lines.
+impl Display for RequestIds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let pairs = [
+            ("request_id", &self.request_id),
+            ("api_request_id", &self.api_request_id),
+            ("trace_id", &self.trace_id),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.as_ref().map(|v| format!("{key}={v}")))
+        .collect::<Vec<_>>();
+
+        write!(f, "{}", pairs.join(" "))
+    }
+}
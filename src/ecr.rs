@@ -0,0 +1,172 @@
+use std::fmt::{Display, Formatter};
+
+/// The regions offering FIPS endpoints for ECR: the US commercial and GovCloud regions.
+pub const FIPS_REGIONS: &[&str] = &[
+    "us-east-1",
+    "us-east-2",
+    "us-west-1",
+    "us-west-2",
+    "us-gov-east-1",
+    "us-gov-west-1",
+];
+
+/// The AWS partitions, which determine the DNS suffix of ECR endpoints.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Partition {
+    /// The standard `aws` partition.
+    Aws,
+    /// The `aws-cn` partition for the China regions.
+    China,
+    /// The `aws-us-gov` partition for the GovCloud (US) regions.
+    GovCloud,
+}
+
+impl Partition {
+    /// Determine the partition of a region from its name, if it is one of the supported ones.
+    ///
+    /// The isolated partitions, such as those of `us-iso-east-1`, `us-isob-east-1`, and
+    /// `eu-isoe-west-1`, are not supported, as their ECR endpoints are not publicly resolvable.
+    pub fn from_region(region: &str) -> Option<Self> {
+        if region
+            .split('-')
+            .nth(1)
+            .is_some_and(|part| part.starts_with("iso"))
+        {
+            None
+        } else if region.starts_with("cn-") {
+            Some(Self::China)
+        } else if region.starts_with("us-gov-") {
+            Some(Self::GovCloud)
+        } else {
+            Some(Self::Aws)
+        }
+    }
+
+    /// The DNS suffix of IPv4-only endpoints.
+    pub fn dns_suffix(&self) -> &'static str {
+        match self {
+            Self::Aws | Self::GovCloud => "amazonaws.com",
+            Self::China => "amazonaws.com.cn",
+        }
+    }
+
+    /// The DNS suffix of dual-stack (IPv4 and IPv6) endpoints.
+    pub fn dual_stack_dns_suffix(&self) -> &'static str {
+        match self {
+            Self::Aws | Self::GovCloud => "on.aws",
+            Self::China => "on.amazonwebservices.com.cn",
+        }
+    }
+}
+
+/// Reasons an ECR registry host cannot be derived.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EcrHostError {
+    InvalidAccountId(String),
+    InvalidRegion(String),
+    /// The region is in a partition, such as an isolated one, whose endpoints are not supported.
+    UnsupportedPartition(String),
+    /// The region offers no FIPS endpoints; see [FIPS_REGIONS].
+    FipsUnsupported(String),
+}
+
+impl Display for EcrHostError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAccountId(account) => {
+                write!(f, "{account:?} is not a 12-digit AWS account ID")
+            }
+            Self::InvalidRegion(region) => write!(f, "{region:?} is not an AWS region"),
+            Self::UnsupportedPartition(region) => {
+                write!(f, "the partition of {region:?} is not supported")
+            }
+            Self::FipsUnsupported(region) => {
+                write!(f, "FIPS endpoints are not available in {region:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EcrHostError {}
+
+/// Derive the host name of the ECR private registry for an account in a region, optionally using
+/// the FIPS and/or dual-stack endpoint:
+///
+///  - `{account}.dkr.ecr.{region}.amazonaws.com`
+///  - `{account}.dkr.ecr-fips.{region}.amazonaws.com`
+///  - `{account}.dkr-ecr.{region}.on.aws`
+///  - `{account}.dkr-ecr-fips.{region}.on.aws`
+///
+/// with the China regions using `amazonaws.com.cn` and `on.amazonwebservices.com.cn` instead.
+/// Regions of the isolated partitions are rejected, as are FIPS endpoints outside of
+/// [FIPS_REGIONS].
+pub fn registry_host(
+    account_id: &str,
+    region: &str,
+    fips: bool,
+    dual_stack: bool,
+) -> Result<String, EcrHostError> {
+    if !is_account_id(account_id) {
+        return Err(EcrHostError::InvalidAccountId(account_id.into()));
+    }
+
+    if !is_region(region) {
+        return Err(EcrHostError::InvalidRegion(region.into()));
+    }
+
+    let partition = Partition::from_region(region)
+        .ok_or_else(|| EcrHostError::UnsupportedPartition(region.into()))?;
+
+    if fips && !FIPS_REGIONS.contains(&region) {
+        return Err(EcrHostError::FipsUnsupported(region.into()));
+    }
+
+    let fips = if fips { "-fips" } else { "" };
+
+    Ok(if dual_stack {
+        format!(
+            "{account_id}.dkr-ecr{fips}.{region}.{}",
+            partition.dual_stack_dns_suffix()
+        )
+    } else {
+        format!(
+            "{account_id}.dkr.ecr{fips}.{region}.{}",
+            partition.dns_suffix()
+        )
+    })
+}
+
+/// Determine whether a host name is that of an ECR private registry, i.e. one which
+/// [registry_host] could have derived.
+pub fn is_ecr_registry_host(host: &str) -> bool {
+    let labels: Vec<&str> = host.split('.').collect();
+
+    let [account, _, _, region, ..] = labels[..] else {
+        return false;
+    };
+
+    // the region is the fourth label of standard endpoints and the third of dual-stack ones
+    [region, labels[2]].into_iter().any(|region| {
+        [(false, false), (true, false), (false, true), (true, true)]
+            .into_iter()
+            .any(|(fips, dual_stack)| {
+                registry_host(account, region, fips, dual_stack).is_ok_and(|h| h == host)
+            })
+    })
+}
+
+/// Whether the value is a 12-digit AWS account ID.
+pub fn is_account_id(value: &str) -> bool {
+    value.len() == 12 && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Whether the value looks like an AWS region name, such as `us-east-1` or `us-gov-west-1`.
+pub fn is_region(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+
+    parts.len() >= 3
+        && parts[..parts.len() - 1]
+            .iter()
+            .all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_lowercase()))
+        && parts[parts.len() - 1].parse::<u8>().is_ok_and(|n| n > 0)
+}
@@ -0,0 +1,131 @@
+use crate::correlation::RequestIds;
+use crate::oci::ErrorCode;
+use crate::paths::PathError;
+use crate::problem::Problem;
+use aws_lambda_events::http::{Method, StatusCode};
+use std::fmt::{Display, Formatter};
+
+/// The URI prefix of the `type` of problems, followed by the [code](RewriteError::code).
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:lambda-ecr-rewrite:problem:";
+
+/// Reasons a request cannot be redirected to the registry.
+///
+/// Each error has a stable [code](RewriteError::code) for use in logs and problem types, and maps
+/// to an HTTP status, an OCI error code, and a message for the client.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RewriteError {
+    /// No registry host is configured.
+    Misconfigured,
+    /// The request method is not allowed by the configured method policy.
+    MethodNotAllowed(Method),
+    /// The request path cannot be safely forwarded to the registry.
+    InvalidPath(PathError),
+    /// The redirect URL cannot be sent in a `Location` header, for instance because the query
+    /// string contains control characters.
+    InvalidLocation,
+    /// The repository is denied by the configured repository policy.
+    RepositoryDenied(String),
+    /// The repository is denied by the configured repository policy, which hides its existence.
+    RepositoryUnknown(String),
+    /// The Lambda event is not an API Gateway request.
+    InvalidEvent,
+    /// An unexpected failure, such as a panic, occurred while handling the request.
+    Internal,
+}
+
+impl RewriteError {
+    /// A stable identifier of the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Misconfigured => "misconfigured",
+            Self::MethodNotAllowed(_) => "method-not-allowed",
+            Self::InvalidPath(_) => "invalid-path",
+            Self::InvalidLocation => "invalid-location",
+            Self::RepositoryDenied(_) => "repository-denied",
+            Self::RepositoryUnknown(_) => "repository-unknown",
+            Self::InvalidEvent => "invalid-event",
+            Self::Internal => "internal",
+        }
+    }
+
+    /// The HTTP status of responses for the error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            Self::InvalidPath(_) | Self::InvalidLocation => StatusCode::BAD_REQUEST,
+            Self::RepositoryDenied(_) => StatusCode::FORBIDDEN,
+            Self::RepositoryUnknown(_) => StatusCode::NOT_FOUND,
+            Self::Misconfigured | Self::InvalidEvent | Self::Internal => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// The OCI error code under which registry clients are told about the error.
+    pub fn oci_code(&self) -> ErrorCode {
+        match self {
+            Self::MethodNotAllowed(_) | Self::InvalidLocation => ErrorCode::Unsupported,
+            Self::InvalidPath(_) => ErrorCode::NameInvalid,
+            Self::RepositoryDenied(_) => ErrorCode::Denied,
+            Self::RepositoryUnknown(_) => ErrorCode::NameUnknown,
+            Self::Misconfigured | Self::InvalidEvent | Self::Internal => ErrorCode::Unknown,
+        }
+    }
+
+    /// Whether the error was caused by the client's request rather than by this service.
+    pub fn is_client_error(&self) -> bool {
+        self.status().is_client_error()
+    }
+
+    /// A URI identifying the type of problem, as the `type` of an RFC 9457 problem.
+    pub fn problem_type(&self) -> String {
+        format!("{PROBLEM_TYPE_PREFIX}{}", self.code())
+    }
+
+    /// A short summary of the type of problem which does not vary between occurrences.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Misconfigured => "Registry Not Configured",
+            Self::MethodNotAllowed(_) => "Method Not Allowed",
+            Self::InvalidPath(_) => "Invalid Path",
+            Self::InvalidLocation => "Invalid Redirect Location",
+            Self::RepositoryDenied(_) => "Repository Denied",
+            Self::RepositoryUnknown(_) => "Repository Not Found",
+            Self::InvalidEvent => "Invalid Event",
+            Self::Internal => "Internal Error",
+        }
+    }
+
+    /// Describe the error as an RFC 9457 problem, identifying the occurrence by request ID.
+    pub fn to_problem(&self, ids: &RequestIds) -> Problem {
+        Problem {
+            type_: self.problem_type(),
+            title: self.title().into(),
+            status: self.status().as_u16(),
+            detail: Some(self.to_string()),
+            instance: ids.primary().map(Into::into),
+            trace_id: ids.trace_id.clone(),
+        }
+    }
+}
+
+impl Display for RewriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Misconfigured => write!(f, "Destination host name not set."),
+            Self::MethodNotAllowed(method) => write!(f, "The {method} method is not allowed."),
+            Self::InvalidPath(e) => write!(f, "{e}"),
+            Self::InvalidLocation => write!(f, "The request cannot be redirected as sent."),
+            Self::RepositoryDenied(name) => {
+                write!(f, "Access to the repository {name} is denied.")
+            }
+            Self::RepositoryUnknown(name) => {
+                write!(f, "The repository {name} is not known to the registry.")
+            }
+            Self::InvalidEvent => write!(f, "Invalid event received."),
+            Self::Internal => write!(f, "An unexpected error occurred."),
+        }
+    }
+}
+
+impl std::error::Error for RewriteError {}
@@ -0,0 +1,27 @@
+use lambda_extension::{Error, Extension};
+
+/// The name of the environment variable containing the host and port of the Lambda runtime API.
+pub const RUNTIME_API_ENV_VAR: &str = "AWS_LAMBDA_RUNTIME_API";
+
+/// The name under which the shutdown extension registers.
+pub const EXTENSION_NAME: &str = "lambda-ecr-rewrite-shutdown";
+
+/// Register an internal extension subscribed to no events with the runtime API at
+/// [RUNTIME_API_ENV_VAR], as Lambda only sends the runtime `SIGTERM` before shutting down an
+/// execution environment when an extension is registered.
+///
+/// This must be called during initialization, before the runtime polls for its first event. A task
+/// is spawned to poll for the extension's events, which never arrive, as Lambda waits for every
+/// extension to do so before the first invocation.
+pub async fn register_shutdown_extension() -> Result<(), Error> {
+    let extension = Extension::new()
+        .with_extension_name(EXTENSION_NAME)
+        .with_events(&[])
+        .register()
+        .await?;
+
+    // only ends when the environment shuts down, or if the runtime API fails
+    tokio::spawn(extension.run());
+
+    Ok(())
+}
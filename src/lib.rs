@@ -1,259 +1,381 @@
+pub mod access;
+pub mod analytics;
+pub mod cache;
+pub mod config;
+pub mod correlation;
+pub mod ecr;
+pub mod error;
+pub mod extension;
+pub mod logging;
+pub mod methods;
+pub mod metrics;
+pub mod negotiation;
+pub mod oci;
+pub mod paths;
+pub mod problem;
+pub mod redaction;
+pub mod registry;
+pub mod repositories;
 pub mod requests;
 pub mod responses;
+pub mod rewriter;
+pub mod source;
+pub mod telemetry;
+pub mod templates;
 #[cfg(test)]
 mod tests;
 
 use aws_lambda_events::apigw::ApiGatewayProxyResponse;
-use lambda_runtime::Context;
-use std::env;
-
 use aws_lambda_events::encodings::Body;
-use aws_lambda_events::http::HeaderValue;
-use parking_lot::RwLock;
+use aws_lambda_events::http::{HeaderMap, HeaderValue};
+use cache::CacheDirectives;
+use correlation::RequestIds;
+use error::RewriteError;
+use methods::MethodPolicy;
+use negotiation::{MediaType, negotiate};
+use oci::{ErrorCode, ErrorResponse};
+use paths::{PathError, normalize_path};
 use requests::ApiGatewayRequestType;
 use responses::{ApiGatewayGenericResponse, ApiGatewayResponseType};
-use std::sync::{LazyLock, OnceLock};
-use std::time::{Duration, Instant};
-
-static REGISTRY_URL: OnceLock<Option<String>> = OnceLock::new();
+use serde::Serialize;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::LazyLock;
+use templates::{DEFAULT_HTML_TEMPLATE, ErrorContext, Template, TemplateValues};
 
-static CACHE_MAX_AGE: OnceLock<usize> = OnceLock::new();
-
-/// If the `DEBUG` environment variable is set to `y | yes | true`, then we enable debug logging.
-static IS_DEBUG: LazyLock<bool> = LazyLock::new(|| {
-    if let Ok(value) = std::env::var("DEBUG") {
-        value.trim().starts_with("y") || value.trim() == "true"
-    } else {
-        false
-    }
+static DEFAULT_HTML: LazyLock<Template> = LazyLock::new(|| {
+    Template::parse(DEFAULT_HTML_TEMPLATE).expect("the default HTML template is valid")
 });
 
-/// The name of the environment variable containing the ECR registry host FQDN.
-pub const ECR_REGISTRY_ENV_VAR: &str = "ECR_REGISTRY_HOST";
-
-/// The name of the environment variable containing the cache max age in seconds to return with
-/// responses.
-pub const CACHE_MAX_AGE_ENV_VAR: &str = "CACHE_MAX_AGE";
-
-/// The default max age in seconds to specify in the `Cache-Control` header.
-pub const CACHE_MAX_AGE_DEFAULT: usize = 60;
-
-/// The minimum amount of time to wait before logging failed requests.
-pub const MIN_LOG_INTERVAL: Duration = Duration::from_secs(60);
-
-static LAST_LOG_TIME: RwLock<Option<Instant>> = RwLock::new(None);
-
-static HTML_ERROR_RESPONSE: &str = r#"<!doctype html>
-<html lang="en-us">
-  <head>
-    <title>Error: 500 (Internal Server Error)</title>
-  </head>
-  <body>
-    <h1>Error: 500 (Internal Server Error)</h1>
-    <p>Destination host name not set.</p>
-  </body>
-</html>"#;
-
-static JSON_ERROR_RESPONSE: &str = r#"{
-  "status": {
-    "code": 500,
-    "msg": "Internal Server Error"
-  },
-  "errors": ["Destination host name not set."]
-}"#;
-
-/// Take an API Gateway proxy request and rewrite it into an API Gateway proxy response containing
-/// the redirect or an error message if no host is defined.
-pub fn rewrite(req: serde_json::Value, _ctx: Context) -> ApiGatewayResponseType {
-    // dump the event if logging is enabled
-    debug_log(|| {
-        format!(
-            "Event Payload: {}",
-            serde_json::to_string(&req).unwrap_or_else(|e| { format!("(error: {e:?})") })
-        )
-    });
+/// Negotiates the media type of a response from the request's `Accept` header, falling back to
+/// the first of the available types if none of them are acceptable.
+pub fn negotiate_media_type(req: &ApiGatewayRequestType, available: &[MediaType]) -> MediaType {
+    let accept = req
+        .headers()
+        .get_all("Accept")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>();
+
+    let accept = (!accept.is_empty()).then(|| accept.join(","));
+
+    negotiate(accept.as_deref(), available)
+        .or_else(|| available.first().copied())
+        .unwrap_or(MediaType::Json)
+}
 
-    let req_backup = req.clone();
+/// Creates a 500 error response for the circumstance in which we lack the
+/// [config::ECR_REGISTRY_ENV_VAR] fqdn of the ECR registry.
+pub fn create_error_response(req: &ApiGatewayRequestType) -> ApiGatewayResponseType {
+    create_rewrite_error_response(req, &RewriteError::Misconfigured, &Default::default())
+}
 
-    // try to get the request as either v1 or v2 of api gateway
-    let req = match serde_json::from_value::<ApiGatewayRequestType>(req) {
-        Ok(req) => req,
-        Err(e) => {
-            eprintln!(
-                "ERROR: Unable to deserialize event as either version of API gateway request: {e:?}"
-            );
-            eprintln!(
-                "Actual payload: {}",
-                serde_json::to_string_pretty(&req_backup)
-                    .unwrap_or_else(|e| format!("(error: {e:?})"))
-            );
-
-            // here we cannot determine what kind of response to issue so we return a v1
-            return ApiGatewayResponseType::V1(ApiGatewayProxyResponse {
-                status_code: 500,
-                headers: Default::default(),
-                multi_value_headers: Default::default(),
-                body: Some("Invalid event received".into()),
-                is_base64_encoded: false,
-            });
-        }
-    };
+/// Creates an error response in the media type negotiated with the client.
+///
+/// Client errors, which registry clients can act upon, are rendered in the OCI format
+/// (`application/json`) by default, and server errors in HTML, but any of them may also be
+/// requested as `application/problem+json` or `text/plain`. HTML and JSON bodies are rendered
+/// from the templates of the context's branding, if any.
+pub fn create_rewrite_error_response(
+    req: &ApiGatewayRequestType,
+    error: &RewriteError,
+    context: &ErrorContext,
+) -> ApiGatewayResponseType {
+    rewrite_error_response(req, error, context).into()
+}
 
-    let resp = if let Some(hostname) = ecr_registry_url() {
-        create_rewrite_response(&req, hostname, cache_max_age())
-    } else {
-        eprintln!(
-            "ERROR: Misconfiguration; please set the {} environment variable to the FQDN of the ECR registry",
-            ECR_REGISTRY_ENV_VAR
-        );
-        create_error_response(&req)
-    };
+/// Creates an error response for an event which could not be handled as an API Gateway request.
+///
+/// As the version of API Gateway is unknown, a v1 response is returned in the OCI format.
+pub fn create_event_error_response(
+    error: &RewriteError,
+    ids: &RequestIds,
+) -> ApiGatewayResponseType {
+    let mut headers = HeaderMap::new();
+
+    headers.insert("Cache-Control", HeaderValue::from_static("no-store"));
+    headers.insert("Content-Type", HeaderValue::from_static("application/json"));
 
-    debug_log(|| {
-        format!(
-            "Response Payload: {}",
-            serde_json::to_string(&resp).unwrap_or_else(|e| { format!("(error: {e:?})") })
-        )
+    let mut resp = ApiGatewayResponseType::V1(ApiGatewayProxyResponse {
+        status_code: error.status().as_u16().into(),
+        headers,
+        multi_value_headers: Default::default(),
+        body: Some(Body::Text(render_oci_error(error, ids))),
+        is_base64_encoded: false,
     });
 
+    insert_request_id_headers(&mut resp, ids);
+
     resp
 }
 
-/// Emit a debug log only if debug logging is enabled.
-pub fn debug_log<S: AsRef<str>>(f: impl FnOnce() -> S) {
-    if *IS_DEBUG {
-        eprintln!("DEBUG: {}", f().as_ref());
+/// Add the headers correlating a response with its invocation, request, and trace.
+pub fn insert_request_id_headers(resp: &mut ApiGatewayResponseType, ids: &RequestIds) {
+    for (name, value) in ids.headers() {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            resp.insert_header(name, value);
+        }
+    }
+}
+
+/// Creates an error response in the OCI distribution format understood by registry clients.
+pub fn create_oci_error_response<S: AsRef<str>>(
+    req: &ApiGatewayRequestType,
+    status_code: i64,
+    code: ErrorCode,
+    message: S,
+) -> ApiGatewayResponseType {
+    oci_error_response(req, status_code, code, message).into()
+}
+
+/// Creates a 405 response listing the allowed methods for a request whose method is not allowed
+/// by the given policy.
+pub fn create_method_not_allowed_response(
+    req: &ApiGatewayRequestType,
+    policy: &MethodPolicy,
+    context: &ErrorContext,
+) -> ApiGatewayResponseType {
+    let mut resp = rewrite_error_response(
+        req,
+        &RewriteError::MethodNotAllowed(req.method().clone()),
+        context,
+    );
+
+    if let Ok(allow) = HeaderValue::from_str(policy.allow_header().as_str()) {
+        resp.headers.insert("Allow", allow);
     }
+
+    resp.into()
 }
 
-/// Only emit logs after a given interval of time, preventing masses of redundant logs.
-pub fn log_infrequently<S: AsRef<str>>(message: S) -> LogStatus {
-    let now = Instant::now();
+fn rewrite_error_response<'a>(
+    req: &'a ApiGatewayRequestType,
+    error: &RewriteError,
+    context: &ErrorContext,
+) -> ApiGatewayGenericResponse<'a> {
+    let media_type = negotiate_media_type(
+        req,
+        if error.is_client_error() {
+            &[
+                MediaType::Json,
+                MediaType::ProblemJson,
+                MediaType::Html,
+                MediaType::Text,
+            ]
+        } else {
+            &[
+                MediaType::Html,
+                MediaType::Json,
+                MediaType::ProblemJson,
+                MediaType::Text,
+            ]
+        },
+    );
 
-    // get a read-only guard
-    let guard = LAST_LOG_TIME.read();
+    let mut resp = ApiGatewayGenericResponse::builder()
+        .req(req)
+        .status_code(error.status().as_u16().into())
+        .build();
 
-    // if LAST_LOG_TIME is unset or it has been MIN_LOG_INTERVAL since the last log, then log and
-    // store the new last log time.
-    if guard.is_none() || now.duration_since(guard.unwrap()).ge(&MIN_LOG_INTERVAL) {
-        eprintln!("{}", message.as_ref());
-        drop(guard);
+    resp.headers
+        .insert("Cache-Control", HeaderValue::from_static("no-store"));
+    resp.headers.insert(
+        "Content-Type",
+        HeaderValue::from_static(media_type.content_type()),
+    );
+    resp.headers
+        .insert("Vary", HeaderValue::from_static("Accept"));
 
-        let mut guard = LAST_LOG_TIME.write();
-        *guard = Some(now);
+    resp.body = Some(Body::Text(match media_type {
+        MediaType::Json => render_json_error(error, context),
+        MediaType::ProblemJson => to_json(&error.to_problem(&context.ids)),
+        MediaType::Html => render_html_error(error, context),
+        MediaType::Text => render_text_error(error, &context.ids),
+    }));
 
-        LogStatus::Emitted
-    } else {
-        LogStatus::Ignored
-    }
+    resp
 }
 
-/// Simple enum representing whether a log message was emitted or ignored.
-#[derive(Debug, Eq, PartialEq)]
-pub enum LogStatus {
-    Emitted,
-    Ignored,
+fn oci_error_response<S: AsRef<str>>(
+    req: &ApiGatewayRequestType,
+    status_code: i64,
+    code: ErrorCode,
+    message: S,
+) -> ApiGatewayGenericResponse<'_> {
+    let mut resp = ApiGatewayGenericResponse::builder()
+        .req(req)
+        .status_code(status_code)
+        .build();
+
+    resp.headers
+        .insert("Cache-Control", HeaderValue::from_static("no-store"));
+    resp.headers
+        .insert("Content-Type", HeaderValue::from_static("application/json"));
+    resp.body = Some(Body::Text(to_json(&ErrorResponse::new(
+        code,
+        message.as_ref(),
+    ))));
+
+    resp
 }
 
-/// Fetch (and cache) the ECR registry host from the [ECR_REGISTRY_ENV_VAR] environment variable.
-pub fn ecr_registry_url() -> Option<&'static String> {
-    REGISTRY_URL
-        .get_or_init(|| env::var(ECR_REGISTRY_ENV_VAR).ok())
-        .as_ref()
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_else(|e| format!("(error: {e:?})"))
 }
 
-/// Determine the `max-age` setting for the `Cache-Control` header.
-pub fn cache_max_age() -> usize {
-    *CACHE_MAX_AGE.get_or_init(|| {
-        if let Ok(v) = env::var(CACHE_MAX_AGE_ENV_VAR) {
-            v.parse().unwrap_or(CACHE_MAX_AGE_DEFAULT)
-        } else {
-            CACHE_MAX_AGE_DEFAULT
-        }
-    })
+/// Render an error as an HTML page for browsers, from the branded template if there is one.
+pub(crate) fn render_html_error(error: &RewriteError, context: &ErrorContext) -> String {
+    let values = TemplateValues::new(error, context);
+
+    match &context.branding.html {
+        Some(template) => template.render(&values, escape_html),
+        None => DEFAULT_HTML.render(&values, escape_html),
+    }
 }
 
-/// Determines whether to serve a JSON response for a given request.
-pub fn should_return_json(req: &ApiGatewayRequestType) -> bool {
-    for header_name in ["Accept", "Content-Type"] {
-        if let Some(value) = req.headers().get(header_name) {
-            // if html is NOT in the content type, return json
-            if !value.to_str().unwrap_or("").contains("html") {
-                return true;
-            }
+/// Render an error as JSON, from the branded template if there is one, or else in the OCI format.
+///
+/// A template which does not render valid JSON falls back to the OCI format.
+fn render_json_error(error: &RewriteError, context: &ErrorContext) -> String {
+    context
+        .branding
+        .json
+        .as_ref()
+        .map(|template| template.render(&TemplateValues::new(error, context), escape_json))
+        .filter(|rendered| serde_json::from_str::<serde::de::IgnoredAny>(rendered).is_ok())
+        .unwrap_or_else(|| render_oci_error(error, &context.ids))
+}
+
+/// Render an error in the OCI format, with the request IDs as its detail.
+fn render_oci_error(error: &RewriteError, ids: &RequestIds) -> String {
+    let mut body = ErrorResponse::new(error.oci_code(), error.to_string());
+
+    if !ids.is_empty() {
+        for e in &mut body.errors {
+            e.detail = serde_json::to_value(ids).ok();
         }
     }
 
-    false
+    to_json(&body)
 }
 
-/// Creates a 500 error response in either JSON or HTML for the circumstance in which we lack the
-/// [ECR_REGISTRY_ENV_VAR] fqdn of the ECR registry.
-pub fn create_error_response(req: &ApiGatewayRequestType) -> ApiGatewayResponseType {
-    let mut resp = ApiGatewayGenericResponse::builder()
-        .req(req)
-        .status_code(500)
-        .build();
+/// Render an error as plain text.
+fn render_text_error(error: &RewriteError, ids: &RequestIds) -> String {
+    let status = error.status();
 
-    if should_return_json(req) {
-        resp.headers
-            .insert("Content-Type", HeaderValue::from_static("application/json"));
-        resp.body = Some(Body::Text(JSON_ERROR_RESPONSE.to_string()))
-    } else {
-        resp.headers.insert(
-            "Content-Type",
-            HeaderValue::from_static("text/html; charset=utf-8"),
-        );
-        resp.body = Some(Body::Text(HTML_ERROR_RESPONSE.to_string()));
+    let mut text = format!(
+        "Error: {} ({})\n\n{error}\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("Error")
+    );
+
+    if let Some(request_id) = ids.primary() {
+        text.push_str(&format!("\nRequest ID: {request_id}\n"));
     }
 
-    resp.into()
+    text
+}
+
+/// Escape text for inclusion in HTML element content or attribute values.
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Escape text for inclusion within a JSON string literal.
+pub(crate) fn escape_json(text: &str) -> String {
+    let quoted = serde_json::to_string(text).unwrap_or_default();
+
+    quoted
+        .strip_prefix('"')
+        .and_then(|q| q.strip_suffix('"'))
+        .unwrap_or_default()
+        .to_string()
 }
 
 /// Creates a 307 rewrite response, redirecting the client to the ECR registry.
+///
+/// Requests whose path cannot be safely normalized are answered with a 400 instead.
 pub fn create_rewrite_response<S: AsRef<str>>(
     req: &ApiGatewayRequestType,
     host: S,
+    base_path: Option<&str>,
     max_age: usize,
 ) -> ApiGatewayResponseType {
+    match redirect_location(req, host, base_path) {
+        Ok(location) => create_redirect_response(req, location, &CacheDirectives::max_age(max_age)),
+        Err(e) => {
+            create_rewrite_error_response(req, &RewriteError::InvalidPath(e), &Default::default())
+        }
+    }
+}
+
+/// Creates a 307 response redirecting the client to the given location, or a 400 if the location
+/// cannot be sent in a header.
+pub fn create_redirect_response<S: AsRef<str>>(
+    req: &ApiGatewayRequestType,
+    location: S,
+    cache_control: &CacheDirectives,
+) -> ApiGatewayResponseType {
+    let Ok(location) = HeaderValue::from_str(location.as_ref()) else {
+        return create_rewrite_error_response(
+            req,
+            &RewriteError::InvalidLocation,
+            &Default::default(),
+        );
+    };
+
     let mut resp = ApiGatewayGenericResponse::builder()
         .req(req)
         .status_code(307)
         .build();
 
-    let path = req
-        .path()
-        .as_ref()
-        .map(|p| {
-            if !p.starts_with("/") {
-                format!("/{}", p)
-            } else {
-                p.to_string()
-            }
-        })
-        .unwrap_or("/".into());
-
-    let query = {
-        let qs = req.query().to_query_string();
-
-        if qs.is_empty() {
-            qs
-        } else {
-            format!("?{}", qs)
-        }
-    };
+    let cache_control = cache_control.to_string();
 
-    let location = format!("https://{host}{path}{query}", host = host.as_ref());
+    if !cache_control.is_empty()
+        && let Ok(cache_control) = HeaderValue::from_str(cache_control.as_str())
+    {
+        resp.headers.insert("Cache-Control", cache_control);
+    }
 
-    resp.status_code = 307;
-    resp.headers.insert(
-        "Cache-Control",
-        HeaderValue::from_str(format!("max-age={max_age}").as_str()).unwrap(),
-    );
-    resp.headers.insert(
-        "Location",
-        HeaderValue::from_str(location.as_str()).unwrap(),
-    );
+    resp.headers.insert("Location", location);
 
     resp.into()
 }
+
+/// Determine the URL on the ECR registry to which a request should be redirected.
+pub fn redirect_location<S: AsRef<str>>(
+    req: &ApiGatewayRequestType,
+    host: S,
+    base_path: Option<&str>,
+) -> Result<String, PathError> {
+    let path = normalize_path(req.registry_path(base_path))?;
+
+    Ok(location_url(host.as_ref(), &path, &req.query_string()))
+}
+
+/// A random number, not suitable for cryptography.
+pub(crate) fn random_u64() -> u64 {
+    // each RandomState is keyed differently, so its hash of anything is a random number
+    RandomState::new().hash_one(())
+}
+
+/// Build a redirect URL from a host, normalized path, and query string.
+pub(crate) fn location_url(host: &str, path: &str, query: &str) -> String {
+    if query.is_empty() {
+        format!("https://{host}{path}")
+    } else {
+        format!("https://{host}{path}?{query}")
+    }
+}
@@ -0,0 +1,412 @@
+use crate::correlation::RequestIds;
+use bon::Builder;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The request header which, carrying the configured debug secret, enables trace logging of the
+/// request.
+pub const DEBUG_HEADER: &str = "X-Debug-Token";
+
+/// The most keys a [LogLimiter] keeps a budget for, bounding its memory when keyed by client.
+pub const LOG_LIMITER_MAX_KEYS: usize = 1024;
+
+/// The severity of a log line, from the most to the least severe.
+#[derive(
+    Debug, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    /// The default level, logging a summary of each request.
+    #[default]
+    Info,
+    /// Additionally logs event and response payloads.
+    Debug,
+    /// Additionally logs how each request was routed.
+    Trace,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Self::Error,
+            "warn" | "warning" => Self::Warn,
+            "info" => Self::Info,
+            "debug" => Self::Debug,
+            "trace" => Self::Trace,
+            _ => {
+                return Err(format!(
+                    "unknown log level {s:?}; expected error, warn, info, debug, or trace"
+                ));
+            }
+        })
+    }
+}
+
+/// Compare secrets in time independent of where they differ.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The fraction of requests to sample, between `0.0` and `1.0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct SampleRate(f64);
+
+impl SampleRate {
+    pub const NEVER: Self = Self(0.0);
+    pub const ALWAYS: Self = Self(1.0);
+
+    pub fn new(rate: f64) -> Result<Self, String> {
+        if (0.0..=1.0).contains(&rate) {
+            Ok(Self(rate))
+        } else {
+            Err(format!("{rate} is not a sample rate between 0.0 and 1.0"))
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        self.0
+    }
+
+    /// Whether to sample an occurrence, at random.
+    pub fn sample(&self) -> bool {
+        match self.0 {
+            rate if rate <= 0.0 => false,
+            rate if rate >= 1.0 => true,
+            rate => (crate::random_u64() as f64 / u64::MAX as f64) < rate,
+        }
+    }
+}
+
+// the rate is never NaN
+impl Eq for SampleRate {}
+
+impl TryFrom<f64> for SampleRate {
+    type Error = String;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<SampleRate> for f64 {
+    fn from(value: SampleRate) -> Self {
+        value.0
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(
+            s.trim()
+                .parse()
+                .map_err(|e| format!("invalid sample rate: {e}"))?,
+        )
+    }
+}
+
+/// The fields of a structured log line.
+///
+/// Fields describing a request are named the same in every line so that CloudWatch Logs Insights
+/// queries can filter and aggregate on them, e.g. `stats avg(latency_ms) by decision, status`.
+#[derive(Debug, Clone, Default, PartialEq, Builder, Serialize)]
+pub struct LogEntry {
+    #[builder(into)]
+    pub message: String,
+    /// The identifiers of the request, as `request_id`, `api_request_id`, and `trace_id`.
+    #[serde(flatten)]
+    #[builder(default)]
+    pub ids: RequestIds,
+    /// The vanity domain the request was made to.
+    #[builder(into)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// The path of the request.
+    #[builder(into)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Whether the request was redirected or rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision: Option<&'static str>,
+    /// The [code](crate::error::RewriteError::code) of the error the request was rejected with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+    /// The HTTP status of the response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// The time taken to handle the request, in milliseconds.
+    #[builder(with = |latency: Duration| latency.as_secs_f64() * 1000.0)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    /// The redirect URL, when tracing.
+    #[builder(into)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// The Lambda event payload, when debugging or if it could not be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<serde_json::Value>,
+    /// The response payload, when debugging.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<serde_json::Value>,
+    /// How many lines with the same key were suppressed by the [LogLimiter] since the last one
+    /// was logged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suppressed: Option<u64>,
+}
+
+impl LogEntry {
+    /// Format the entry as a single line of JSON at the given level.
+    pub fn to_line(&self, level: Level) -> String {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            level: Level,
+            #[serde(flatten)]
+            entry: &'a LogEntry,
+        }
+
+        serde_json::to_string(&Line { level, entry: self }).unwrap_or_else(|e| {
+            format!(
+                r#"{{"level":"{level}","message":"unable to format log line: {}"}}"#,
+                crate::escape_json(&e.to_string())
+            )
+        })
+    }
+}
+
+impl From<String> for LogEntry {
+    fn from(message: String) -> Self {
+        Self::builder().message(message).build()
+    }
+}
+
+impl From<&str> for LogEntry {
+    fn from(message: &str) -> Self {
+        Self::builder().message(message).build()
+    }
+}
+
+/// Writes structured log lines at or above a minimum level to standard error, from which Lambda
+/// sends them to CloudWatch Logs.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Logger {
+    level: Level,
+}
+
+impl Logger {
+    pub fn new(level: Level) -> Self {
+        Self { level }
+    }
+
+    /// The least severe level which is logged.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Whether lines at the given level are logged.
+    pub fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    /// Log an entry at the given level, building it only if the level is enabled.
+    pub fn log<E: Into<LogEntry>>(&self, level: Level, f: impl FnOnce() -> E) {
+        if self.enabled(level) {
+            eprintln!("{}", f().into().to_line(level));
+        }
+    }
+
+    pub fn error(&self, entry: impl Into<LogEntry>) {
+        self.log(Level::Error, || entry)
+    }
+
+    pub fn warn(&self, entry: impl Into<LogEntry>) {
+        self.log(Level::Warn, || entry)
+    }
+
+    pub fn info(&self, entry: impl Into<LogEntry>) {
+        self.log(Level::Info, || entry)
+    }
+
+    /// Log an entry at the given level if the limiter has budget left for its key, noting how many
+    /// lines with that key were suppressed before it.
+    pub fn log_limited<E: Into<LogEntry>>(
+        &self,
+        limiter: &LogLimiter,
+        key: &str,
+        limit: &LogLimit,
+        level: Level,
+        f: impl FnOnce() -> E,
+    ) -> LogStatus {
+        if !self.enabled(level) {
+            return LogStatus::Ignored;
+        }
+
+        let status = limiter.check(key, limit);
+
+        if let LogStatus::Emitted { suppressed } = status {
+            self.log(level, || LogEntry {
+                suppressed: (suppressed > 0).then_some(suppressed),
+                ..f().into()
+            });
+        }
+
+        status
+    }
+}
+
+/// Whether a rate-limited log line was emitted or ignored.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LogStatus {
+    /// The line was emitted, after the given number of lines with the same key were ignored.
+    Emitted {
+        suppressed: u64,
+    },
+    Ignored,
+}
+
+/// The budget of each key of a [LogLimiter]: bursts of up to `burst` lines, refilling at `burst`
+/// lines per `interval` seconds.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogLimit {
+    #[builder(default = 5)]
+    pub burst: u32,
+    #[builder(default = 60)]
+    pub interval: u64,
+}
+
+impl Default for LogLimit {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl LogLimit {
+    /// The number of lines the budget refills by per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.burst) / self.interval.max(1) as f64
+    }
+}
+
+/// Parses `<burst>/<interval>`, such as `5/60` for five lines a minute.
+impl FromStr for LogLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (burst, interval) = s
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| format!("{s:?} is not of the form <burst>/<interval seconds>"))?;
+
+        Ok(Self {
+            burst: burst
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid burst: {e}"))?,
+            interval: interval
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid interval: {e}"))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    suppressed: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &LogLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * limit.rate()).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+}
+
+/// Limits how often log lines are emitted with a token bucket for each key, such as an error code
+/// or a client address, so that one noisy source cannot drown out or suppress the others.
+#[derive(Debug, Default)]
+pub struct LogLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl LogLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a line from the budget of the key, if there is one left.
+    pub fn check(&self, key: &str, limit: &LogLimit) -> LogStatus {
+        self.check_at(key, limit, Instant::now())
+    }
+
+    /// Take a line from the budget of the key as of the given time, if there is one left.
+    ///
+    /// Once [LOG_LIMITER_MAX_KEYS] keys are tracked, keys whose budget is full and which have
+    /// nothing suppressed are forgotten; if none can be, lines with new keys are ignored.
+    pub fn check_at(&self, key: &str, limit: &LogLimit, now: Instant) -> LogStatus {
+        let mut buckets = self.buckets.lock();
+
+        if !buckets.contains_key(key) && buckets.len() >= LOG_LIMITER_MAX_KEYS {
+            buckets.retain(|_, bucket| {
+                bucket.refill(limit, now);
+                bucket.suppressed > 0 || bucket.tokens < f64::from(limit.burst)
+            });
+
+            if buckets.len() >= LOG_LIMITER_MAX_KEYS {
+                return LogStatus::Ignored;
+            }
+        }
+
+        let bucket = buckets.entry(key.into()).or_insert(Bucket {
+            tokens: f64::from(limit.burst),
+            updated: now,
+            suppressed: 0,
+        });
+
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+
+            LogStatus::Emitted {
+                suppressed: std::mem::take(&mut bucket.suppressed),
+            }
+        } else {
+            bucket.suppressed += 1;
+
+            LogStatus::Ignored
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use aws_lambda_events::http::Method;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The set of HTTP methods which will be redirected to the registry.
+///
+/// Requests using any other method are answered with a 405 so that, for instance, a `DELETE`
+/// cannot accidentally be issued against the registry through the vanity domain.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "MethodPolicyValue", into = "Vec<String>")]
+pub struct MethodPolicy {
+    allowed: Vec<Method>,
+}
+
+impl MethodPolicy {
+    /// Allow only pulls: `GET` and `HEAD`.
+    pub fn read_only() -> Self {
+        Self::new([Method::GET, Method::HEAD])
+    }
+
+    /// Allow pulls and pushes, but not deletes.
+    pub fn read_write() -> Self {
+        Self::new([
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+        ])
+    }
+
+    /// Allow exactly the given methods.
+    pub fn new(allowed: impl IntoIterator<Item = Method>) -> Self {
+        let mut policy = Self { allowed: vec![] };
+
+        for method in allowed {
+            if !policy.allowed.contains(&method) {
+                policy.allowed.push(method);
+            }
+        }
+
+        policy
+    }
+
+    /// Whether requests with the given method should be redirected.
+    pub fn allows(&self, method: &Method) -> bool {
+        self.allowed.contains(method)
+    }
+
+    /// The allowed methods, in the order they were specified.
+    pub fn allowed(&self) -> &[Method] {
+        &self.allowed
+    }
+
+    /// The value of the `Allow` header to send with 405 responses.
+    pub fn allow_header(&self) -> String {
+        self.allowed
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Default for MethodPolicy {
+    fn default() -> Self {
+        Self::read_only()
+    }
+}
+
+impl Display for MethodPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.allow_header())
+    }
+}
+
+/// Parses either a preset (`read-only` or `read-write`) or a comma-separated list of methods such
+/// as `GET,HEAD,PUT`.
+impl FromStr for MethodPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "read-only" | "readonly" => return Ok(Self::read_only()),
+            "read-write" | "readwrite" => return Ok(Self::read_write()),
+            _ => {}
+        }
+
+        let methods = s
+            .split(',')
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .map(|m| {
+                Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                    .map_err(|_| format!("invalid HTTP method: {m:?}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if methods.is_empty() {
+            Err("no HTTP methods specified".into())
+        } else {
+            Ok(Self::new(methods))
+        }
+    }
+}
+
+/// The serialized form of a [MethodPolicy]: either a string as accepted by [MethodPolicy::from_str]
+/// or a list of methods.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MethodPolicyValue {
+    Str(String),
+    List(Vec<String>),
+}
+
+impl TryFrom<MethodPolicyValue> for MethodPolicy {
+    type Error = String;
+
+    fn try_from(value: MethodPolicyValue) -> Result<Self, Self::Error> {
+        match value {
+            MethodPolicyValue::Str(s) => s.parse(),
+            MethodPolicyValue::List(methods) => methods.join(",").parse(),
+        }
+    }
+}
+
+impl From<MethodPolicy> for Vec<String> {
+    fn from(value: MethodPolicy) -> Self {
+        value.allowed.iter().map(|m| m.to_string()).collect()
+    }
+}
@@ -0,0 +1,174 @@
+use bon::Builder;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The CloudWatch namespace of metrics unless one is configured.
+pub const DEFAULT_METRICS_NAMESPACE: &str = "LambdaEcrRewrite";
+
+/// The event source of events which are not API Gateway requests.
+pub const UNKNOWN_EVENT_SOURCE: &str = "unknown";
+
+/// The dimension sets under which every metric is published, each only when all of its dimensions
+/// have a value.
+///
+/// Sets including any of [REDIRECT_DIMENSIONS] are only published for redirected requests, and
+/// those including any of [ALLOW_LIST_DIMENSIONS] only if an allow list bounds what is redirected.
+const DIMENSION_SETS: &[&[&str]] = &[
+    &["EventSource"],
+    &["StatusCode"],
+    &["Endpoint"],
+    &["Host"],
+    &["Host", "Repository"],
+];
+
+/// The dimensions whose values come from the client, which are only used as dimensions of
+/// requests that were redirected, having passed the repository policy. They are recorded as plain
+/// properties of other requests.
+const REDIRECT_DIMENSIONS: &[&str] = &["Host", "Repository"];
+
+/// The dimensions which clients could otherwise give any number of values, creating a metric
+/// each: without an allow list, any repository name is redirected. They are only used as
+/// dimensions if the repository policy has an allow list, and are otherwise plain properties.
+const ALLOW_LIST_DIMENSIONS: &[&str] = &["Repository"];
+
+/// Whether and where to publish metrics.
+#[derive(Debug, Clone, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Whether to write a line of metrics in the CloudWatch Embedded Metric Format to standard
+    /// output for each request.
+    #[builder(default)]
+    pub enabled: bool,
+    /// The CloudWatch namespace of the metrics.
+    #[builder(into, default = DEFAULT_METRICS_NAMESPACE)]
+    pub namespace: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl MetricsConfig {
+    /// Write the metrics of a request to standard output, from which Lambda sends them to
+    /// CloudWatch Logs to be extracted, if enabled.
+    pub fn emit(&self, metrics: &RequestMetrics) {
+        if self.enabled {
+            println!("{}", metrics.to_emf(&self.namespace, SystemTime::now()));
+        }
+    }
+}
+
+/// What happened to a single request, as recorded in metrics.
+#[derive(Debug, Clone, Default, PartialEq, Builder)]
+pub struct RequestMetrics {
+    /// The kind of event the request arrived in, such as `apigateway-v2`.
+    pub event_source: &'static str,
+    /// The vanity domain the request was made to.
+    #[builder(into)]
+    pub host: Option<String>,
+    /// The repository the request addressed.
+    #[builder(into)]
+    pub repository: Option<String>,
+    /// The [kind](crate::registry::EndpointKind) of registry endpoint the request addressed.
+    pub endpoint: Option<&'static str>,
+    /// The HTTP status of the response.
+    pub status: u16,
+    /// Whether the request was redirected to the registry.
+    #[builder(default)]
+    pub redirected: bool,
+    /// Whether the repository policy has an allow list, bounding the repositories redirected to.
+    #[builder(default)]
+    pub allow_listed: bool,
+    /// Whether the request failed because of the configuration.
+    #[builder(default)]
+    pub config_error: bool,
+    /// The time taken to handle the request.
+    pub latency: Duration,
+}
+
+impl RequestMetrics {
+    /// The metrics as a CloudWatch Embedded Metric Format object.
+    pub fn to_emf(&self, namespace: &str, timestamp: SystemTime) -> Value {
+        let count = |condition: bool| u8::from(condition);
+
+        let mut root = Map::new();
+
+        let dimensions = [
+            ("EventSource", Some(self.event_source.to_string())),
+            ("StatusCode", Some(self.status.to_string())),
+            ("Endpoint", self.endpoint.map(Into::into)),
+            ("Host", self.host.clone()),
+            ("Repository", self.repository.clone()),
+        ];
+
+        for (name, value) in dimensions {
+            if let Some(value) = value {
+                root.insert(name.into(), value.into());
+            }
+        }
+
+        let dimension_sets = DIMENSION_SETS
+            .iter()
+            .filter(|set| set.iter().all(|name| root.contains_key(*name)))
+            .filter(|set| {
+                self.redirected || !set.iter().any(|name| REDIRECT_DIMENSIONS.contains(name))
+            })
+            .filter(|set| {
+                self.allow_listed || !set.iter().any(|name| ALLOW_LIST_DIMENSIONS.contains(name))
+            })
+            .collect::<Vec<_>>();
+
+        let metrics = [
+            ("Requests", "Count", json!(1)),
+            ("Redirects", "Count", json!(count(self.redirected))),
+            ("Errors", "Count", json!(count(!self.redirected))),
+            (
+                "ClientErrors",
+                "Count",
+                json!(count((400..500).contains(&self.status))),
+            ),
+            (
+                "ServerErrors",
+                "Count",
+                json!(count((500..600).contains(&self.status))),
+            ),
+            ("ConfigErrors", "Count", json!(count(self.config_error))),
+            (
+                "Latency",
+                "Milliseconds",
+                json!(self.latency.as_secs_f64() * 1000.0),
+            ),
+        ];
+
+        let definitions = metrics
+            .iter()
+            .map(|(name, unit, _)| json!({"Name": name, "Unit": unit}))
+            .collect::<Vec<_>>();
+
+        for (name, _, value) in metrics {
+            root.insert(name.into(), value);
+        }
+
+        let timestamp = timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        root.insert(
+            "_aws".into(),
+            json!({
+                "Timestamp": timestamp,
+                "CloudWatchMetrics": [{
+                    "Namespace": namespace,
+                    "Dimensions": dimension_sets,
+                    "Metrics": definitions,
+                }],
+            }),
+        );
+
+        Value::Object(root)
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::{Display, Formatter};
+
+/// The media types in which responses can be rendered.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum MediaType {
+    /// `text/html`, for browsers.
+    Html,
+    /// `application/json`
+    Json,
+    /// `application/problem+json`, as specified by RFC 9457.
+    ProblemJson,
+    /// `text/plain`
+    Text,
+}
+
+impl MediaType {
+    /// The `type/subtype` essence of the media type.
+    pub fn essence(&self) -> &'static str {
+        match self {
+            Self::Html => "text/html",
+            Self::Json => "application/json",
+            Self::ProblemJson => "application/problem+json",
+            Self::Text => "text/plain",
+        }
+    }
+
+    /// The value of the `Content-Type` header for a response of this media type.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Html => "text/html; charset=utf-8",
+            Self::Json => "application/json",
+            Self::ProblemJson => "application/problem+json",
+            Self::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+impl Display for MediaType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.essence())
+    }
+}
+
+/// A media range of an `Accept` header, such as `text/*;q=0.8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    pub type_: String,
+    pub subtype: String,
+    /// The quality (`q`) weight in the range `0.0..=1.0`, defaulting to `1.0`.
+    pub quality: f32,
+}
+
+impl MediaRange {
+    /// Parse a single media range, returning `None` if it is malformed.
+    pub fn parse(range: &str) -> Option<Self> {
+        let mut parts = range.split(';');
+
+        let (type_, subtype) = parts.next()?.trim().split_once('/')?;
+
+        if !is_token(type_) || !is_token(subtype) || (type_ == "*" && subtype != "*") {
+            return None;
+        }
+
+        let mut quality = 1.0;
+
+        for param in parts {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+
+            if name.trim().eq_ignore_ascii_case("q") {
+                quality = parse_quality(value.trim())?;
+            }
+        }
+
+        Some(Self {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            quality,
+        })
+    }
+
+    /// How specifically the range matches the media type, if at all: `2` for an exact match,
+    /// `1` for `type/*`, and `0` for `*/*`.
+    ///
+    /// [MediaType::Json] also matches other `application/*+json` types with a specificity of `1`,
+    /// such as the manifest types registry clients accept, e.g.
+    /// `application/vnd.docker.distribution.manifest.v2+json`, as they understand JSON bodies.
+    pub fn specificity(&self, media_type: MediaType) -> Option<u8> {
+        let (type_, subtype) = media_type.essence().split_once('/')?;
+
+        match (self.type_.as_str(), self.subtype.as_str()) {
+            ("*", "*") => Some(0),
+            (t, "*") if t == type_ => Some(1),
+            (t, s) if t == type_ && s == subtype => Some(2),
+            ("application", s)
+                if media_type == MediaType::Json && s.ends_with("+json") && !is_rendered(s) =>
+            {
+                Some(1)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parse the media ranges of an `Accept` header, skipping any which are malformed.
+pub fn parse_accept(accept: &str) -> Vec<MediaRange> {
+    accept.split(',').filter_map(MediaRange::parse).collect()
+}
+
+/// Select the media type in which to respond as specified by RFC 9110 section 12.5.1.
+///
+/// Each available media type is weighted by the quality of the most specific range matching it,
+/// and the one with the highest non-zero quality wins, with ties going to the earlier of the
+/// available types. Without an `Accept` header, the first available type is chosen. `None` is
+/// returned if the header rules out all of the available types.
+pub fn negotiate(accept: Option<&str>, available: &[MediaType]) -> Option<MediaType> {
+    let Some(accept) = accept else {
+        return available.first().copied();
+    };
+
+    let ranges = parse_accept(accept);
+
+    let mut best: Option<(MediaType, f32)> = None;
+
+    for &media_type in available {
+        let quality = ranges
+            .iter()
+            .filter_map(|range| Some((range.specificity(media_type)?, range.quality)))
+            .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+            .map(|(_, quality)| quality)
+            .unwrap_or(0.0);
+
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((media_type, quality));
+        }
+    }
+
+    best.map(|(media_type, _)| media_type)
+}
+
+/// Parse a quality value: `0` or `1`, or a decimal between them with up to three digits.
+fn parse_quality(value: &str) -> Option<f32> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+
+    let valid = match whole {
+        "0" => fraction.len() <= 3 && fraction.bytes().all(|b| b.is_ascii_digit()),
+        "1" => fraction.len() <= 3 && fraction.bytes().all(|b| b == b'0'),
+        _ => false,
+    };
+
+    valid.then(|| value.parse().ok()).flatten()
+}
+
+/// Whether an `application` subtype is one of the media types responses are rendered in.
+fn is_rendered(subtype: &str) -> bool {
+    [MediaType::Json, MediaType::ProblemJson]
+        .iter()
+        .any(|media_type| media_type.essence().strip_prefix("application/") == Some(subtype))
+}
+
+/// Whether the value is a non-empty RFC 9110 token.
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
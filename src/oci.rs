@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// Error codes defined by the OCI distribution specification.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BlobUnknown,
+    BlobUploadInvalid,
+    BlobUploadUnknown,
+    DigestInvalid,
+    ManifestBlobUnknown,
+    ManifestInvalid,
+    ManifestUnknown,
+    NameInvalid,
+    NameUnknown,
+    SizeInvalid,
+    Unauthorized,
+    Denied,
+    Unsupported,
+    TooManyRequests,
+    /// Not defined by the specification, but used by the reference registry implementation for
+    /// unexpected server errors and understood by clients.
+    Unknown,
+}
+
+/// A single error within an OCI error response body.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<serde_json::Value>,
+}
+
+/// An OCI error response body, as understood by registry clients such as `docker`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub errors: Vec<Error>,
+}
+
+impl ErrorResponse {
+    /// Create an error response containing a single error without any detail.
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            errors: vec![Error {
+                code,
+                message: message.into(),
+                detail: None,
+            }],
+        }
+    }
+}
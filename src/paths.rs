@@ -0,0 +1,77 @@
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, percent_encode};
+use std::fmt::{Display, Formatter};
+
+/// Characters to percent-encode in a path segment: everything but the RFC 3986 `pchar` set, i.e.
+/// unreserved characters, sub-delimiters, `:` and `@`.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'!')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'*')
+    .remove(b'+')
+    .remove(b',')
+    .remove(b';')
+    .remove(b'=')
+    .remove(b':')
+    .remove(b'@');
+
+/// Reasons a request path cannot be safely forwarded to the registry.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PathError {
+    /// The path starts with `//` or `/\`, which a client may resolve as a protocol-relative
+    /// reference to another host.
+    ProtocolRelative,
+    /// The path contains a `.` or `..` segment, either literally or percent-encoded.
+    DotSegment,
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ProtocolRelative => write!(f, "Protocol-relative paths are not allowed."),
+            Self::DotSegment => write!(f, "Paths may not contain '.' or '..' segments."),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Normalize a request path for use in a `Location` header.
+///
+/// The path is made absolute, duplicate slashes are collapsed (a trailing slash is kept, as the
+/// registry treats `/v2/` and `/v2` differently), and each segment is decoded and re-encoded per
+/// RFC 3986 so that the result is always a valid header value. Dot segments and
+/// protocol-relative paths are rejected rather than resolved.
+pub fn normalize_path(path: &str) -> Result<String, PathError> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+
+    if path.starts_with('/') || path.starts_with('\\') {
+        return Err(PathError::ProtocolRelative);
+    }
+
+    let mut normalized = String::with_capacity(path.len() + 1);
+
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let decoded: Vec<u8> = percent_decode_str(segment).collect();
+
+        if decoded == b"." || decoded == b".." {
+            return Err(PathError::DotSegment);
+        }
+
+        normalized.push('/');
+        normalized.extend(percent_encode(&decoded, PATH_SEGMENT));
+    }
+
+    if normalized.is_empty() || path.ends_with('/') {
+        normalized.push('/');
+    }
+
+    Ok(normalized)
+}
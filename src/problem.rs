@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// A problem details object as specified by RFC 9457, served as `application/problem+json` to
+/// API consumers which are not registry clients.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    /// A URI identifying the type of problem.
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// A short summary of the type of problem.
+    pub title: String,
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// An explanation specific to this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Identifies this occurrence of the problem; the Lambda request ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// An extension member carrying the X-Ray trace ID of the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
@@ -0,0 +1,165 @@
+use crate::logging::DEBUG_HEADER;
+use bon::Builder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// The value which redacted values are replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// The headers redacted by default: credentials, cookies, and client addresses.
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "Authorization",
+    "Proxy-Authorization",
+    "Cookie",
+    "Set-Cookie",
+    "X-Amz-Security-Token",
+    "X-Api-Key",
+    "X-Forwarded-For",
+    DEBUG_HEADER,
+];
+
+/// The JSON paths redacted by default: cookies, query strings, which may carry tokens, source IPs,
+/// and caller identities.
+pub const DEFAULT_REDACTED_PATHS: &[&str] = &[
+    "cookies",
+    "queryStringParameters",
+    "multiValueQueryStringParameters",
+    "rawQueryString",
+    "requestContext.authorizer",
+    "requestContext.authentication",
+    "requestContext.identity.accessKey",
+    "requestContext.identity.apiKey",
+    "requestContext.identity.caller",
+    "requestContext.identity.clientCert",
+    "requestContext.identity.cognitoAuthenticationProvider",
+    "requestContext.identity.cognitoAuthenticationType",
+    "requestContext.identity.cognitoIdentityId",
+    "requestContext.identity.cognitoIdentityPoolId",
+    "requestContext.identity.sourceIp",
+    "requestContext.identity.user",
+    "requestContext.identity.userArn",
+    "requestContext.http.sourceIp",
+];
+
+/// The paths of the query string in events: redacting any of them also masks the query of
+/// `Location` headers and logged redirect URLs, into which the query is copied.
+pub const QUERY_PATHS: &[&str] = &[
+    "queryStringParameters",
+    "multiValueQueryStringParameters",
+    "rawQueryString",
+];
+
+/// What to mask in event and response payloads before they are logged.
+///
+/// Headers are matched case-insensitively in the `headers` and `multiValueHeaders` of a payload.
+/// Paths are dot-separated object keys, in which `*` matches any key or array element, such as
+/// `requestContext.identity.sourceIp`. Setting either list replaces its defaults. If any of the
+/// [QUERY_PATHS] is redacted, so is the query of `Location` headers.
+#[derive(Debug, Clone, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Redaction {
+    #[builder(default = to_strings(DEFAULT_REDACTED_HEADERS))]
+    pub headers: Vec<String>,
+    #[builder(default = to_strings(DEFAULT_REDACTED_PATHS))]
+    pub paths: Vec<String>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Redaction {
+    /// Mask the configured headers and paths of a payload in place.
+    pub fn redact(&self, payload: &mut Value) {
+        for key in ["headers", "multiValueHeaders"] {
+            if let Some(Value::Object(headers)) = payload.get_mut(key) {
+                for (name, value) in headers.iter_mut() {
+                    if self.headers.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+                        mask(value);
+                    } else if name.eq_ignore_ascii_case("location") {
+                        self.redact_location(value);
+                    }
+                }
+            }
+        }
+
+        for path in &self.paths {
+            redact_path(payload, &path.split('.').collect::<Vec<_>>());
+        }
+    }
+
+    /// A URL with its query masked if query strings are redacted, such as a redirect location,
+    /// which carries the query of the request.
+    pub fn redacted_url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        match url.split_once('?') {
+            Some((base, _)) if self.redacts_query() => Cow::Owned(format!("{base}?{REDACTED}")),
+            _ => Cow::Borrowed(url),
+        }
+    }
+
+    fn redacts_query(&self) -> bool {
+        self.paths
+            .iter()
+            .any(|path| QUERY_PATHS.contains(&path.as_str()))
+    }
+
+    fn redact_location(&self, value: &mut Value) {
+        match value {
+            Value::Array(values) => values.iter_mut().for_each(|v| self.redact_location(v)),
+            Value::String(url) => {
+                if let Cow::Owned(redacted) = self.redacted_url(url) {
+                    *url = redacted;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A redacted copy of a payload.
+    pub fn redacted(&self, payload: &Value) -> Value {
+        let mut payload = payload.clone();
+        self.redact(&mut payload);
+        payload
+    }
+
+    /// The paths which cannot match anything as they contain an empty segment.
+    pub(crate) fn invalid_paths(&self) -> impl Iterator<Item = &str> {
+        self.paths
+            .iter()
+            .map(String::as_str)
+            .filter(|path| path.split('.').any(str::is_empty))
+    }
+}
+
+/// Replace a present value with [REDACTED], element by element for arrays so their shape is kept.
+fn mask(value: &mut Value) {
+    match value {
+        Value::Null => {}
+        Value::Array(values) => values.iter_mut().for_each(mask),
+        value => *value = REDACTED.into(),
+    }
+}
+
+fn redact_path(value: &mut Value, segments: &[&str]) {
+    let Some((segment, rest)) = segments.split_first() else {
+        return mask(value);
+    };
+
+    match (value, *segment) {
+        (Value::Object(map), "*") => map.values_mut().for_each(|v| redact_path(v, rest)),
+        (Value::Array(values), "*") => values.iter_mut().for_each(|v| redact_path(v, rest)),
+        (Value::Object(map), key) => {
+            if let Some(v) = map.get_mut(key) {
+                redact_path(v, rest)
+            }
+        }
+        _ => {}
+    }
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|&v| v.into()).collect()
+}
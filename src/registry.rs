@@ -0,0 +1,220 @@
+use std::fmt::{Display, Formatter};
+
+/// A manifest reference: either a mutable tag or an immutable content digest.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Reference {
+    Tag(String),
+    Digest(String),
+}
+
+impl Reference {
+    /// Parse a reference, treating anything of the form `algorithm:hex` as a digest.
+    pub fn parse(reference: &str) -> Self {
+        if is_digest(reference) {
+            Self::Digest(reference.into())
+        } else {
+            Self::Tag(reference.into())
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Tag(s) | Self::Digest(s) => s,
+        }
+    }
+
+    pub fn is_digest(&self) -> bool {
+        matches!(self, Self::Digest(_))
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The OCI distribution API endpoint addressed by a request path.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Endpoint {
+    /// `/v2/`, used by clients to check API support and authenticate.
+    Base,
+    /// `/v2/_catalog`
+    Catalog,
+    /// `/v2/{repository}/tags/list`
+    Tags { repository: String },
+    /// `/v2/{repository}/manifests/{reference}`
+    Manifest {
+        repository: String,
+        reference: Reference,
+    },
+    /// `/v2/{repository}/blobs/{digest}`
+    Blob { repository: String, digest: String },
+    /// `/v2/{repository}/blobs/uploads/...`
+    BlobUpload { repository: String },
+    /// `/v2/{repository}/referrers/{digest}`
+    Referrers { repository: String, digest: String },
+    /// Anything else, including paths outside of `/v2/`.
+    Other,
+}
+
+/// The kind of an [Endpoint], without its parameters.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum EndpointKind {
+    Base,
+    Catalog,
+    Tags,
+    Manifest,
+    Blob,
+    BlobUpload,
+    Referrers,
+    Other,
+}
+
+impl EndpointKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Base => "base",
+            Self::Catalog => "catalog",
+            Self::Tags => "tags",
+            Self::Manifest => "manifest",
+            Self::Blob => "blob",
+            Self::BlobUpload => "blob_upload",
+            Self::Referrers => "referrers",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl Display for EndpointKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Endpoint {
+    /// Determine the endpoint addressed by a normalized request path.
+    pub fn parse(path: &str) -> Self {
+        let Some(rest) = path
+            .strip_prefix("/v2")
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        else {
+            return Self::Other;
+        };
+
+        let segments: Vec<&str> = rest.split('/').filter(|s| !s.is_empty()).collect();
+
+        match segments[..] {
+            [] => return Self::Base,
+            ["_catalog"] => return Self::Catalog,
+            _ => {}
+        }
+
+        // repository names may contain slashes, so match the route from the end of the path
+        let repository = |n: usize| -> Option<String> {
+            (segments.len() > n).then(|| segments[..segments.len() - n].join("/"))
+        };
+
+        let endpoint = match segments[..] {
+            [.., "tags", "list"] => repository(2).map(|repository| Self::Tags { repository }),
+            [.., "manifests", reference] => repository(2).map(|repository| Self::Manifest {
+                repository,
+                reference: Reference::parse(reference),
+            }),
+            [.., "referrers", digest] => repository(2).map(|repository| Self::Referrers {
+                repository,
+                digest: digest.into(),
+            }),
+            [.., "blobs", "uploads"] => {
+                repository(2).map(|repository| Self::BlobUpload { repository })
+            }
+            [.., "blobs", "uploads", _] => {
+                repository(3).map(|repository| Self::BlobUpload { repository })
+            }
+            [.., "blobs", digest] => repository(2).map(|repository| Self::Blob {
+                repository,
+                digest: digest.into(),
+            }),
+            _ => None,
+        };
+
+        endpoint.unwrap_or(Self::Other)
+    }
+
+    pub fn kind(&self) -> EndpointKind {
+        match self {
+            Self::Base => EndpointKind::Base,
+            Self::Catalog => EndpointKind::Catalog,
+            Self::Tags { .. } => EndpointKind::Tags,
+            Self::Manifest { .. } => EndpointKind::Manifest,
+            Self::Blob { .. } => EndpointKind::Blob,
+            Self::BlobUpload { .. } => EndpointKind::BlobUpload,
+            Self::Referrers { .. } => EndpointKind::Referrers,
+            Self::Other => EndpointKind::Other,
+        }
+    }
+
+    /// The repository the endpoint addresses, if any.
+    pub fn repository(&self) -> Option<&str> {
+        match self {
+            Self::Tags { repository }
+            | Self::Manifest { repository, .. }
+            | Self::Blob { repository, .. }
+            | Self::BlobUpload { repository }
+            | Self::Referrers { repository, .. } => Some(repository),
+            Self::Base | Self::Catalog | Self::Other => None,
+        }
+    }
+
+    /// The manifest reference the endpoint addresses, if any.
+    pub fn reference(&self) -> Option<&Reference> {
+        match self {
+            Self::Manifest { reference, .. } => Some(reference),
+            _ => None,
+        }
+    }
+
+    /// The image the endpoint addresses, as it would be referenced by a client: the repository,
+    /// followed by `:tag` or `@digest` if the endpoint addresses one.
+    pub fn image(&self) -> Option<String> {
+        match self {
+            Self::Manifest {
+                repository,
+                reference: Reference::Tag(tag),
+            } => Some(format!("{repository}:{tag}")),
+            Self::Manifest {
+                repository,
+                reference: Reference::Digest(digest),
+            }
+            | Self::Blob { repository, digest }
+            | Self::Referrers { repository, digest } => Some(format!("{repository}@{digest}")),
+            Self::Tags { repository } | Self::BlobUpload { repository } => Some(repository.clone()),
+            Self::Base | Self::Catalog | Self::Other => None,
+        }
+    }
+
+    /// Whether the endpoint addresses content by digest, such that its response never changes.
+    pub fn is_content_addressed(&self) -> bool {
+        match self {
+            Self::Manifest { reference, .. } => reference.is_digest(),
+            Self::Blob { digest, .. } => is_digest(digest),
+            _ => false,
+        }
+    }
+}
+
+/// Whether a reference is a digest of the form `algorithm:encoded`.
+fn is_digest(reference: &str) -> bool {
+    let Some((algorithm, encoded)) = reference.split_once(':') else {
+        return false;
+    };
+
+    !algorithm.is_empty()
+        && !encoded.is_empty()
+        && algorithm
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"+._-".contains(&b))
+        && encoded
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"=_-".contains(&b))
+}
@@ -0,0 +1,291 @@
+use crate::error::RewriteError;
+use crate::registry::{Endpoint, Reference};
+use bon::Builder;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The prefix marking a pattern as a regular expression rather than a glob.
+pub const REGEX_PREFIX: &str = "regex:";
+
+/// Which repositories, and optionally tags, may be reached through the vanity domain.
+///
+/// A request addressing a repository is redirected only if its repository matches an
+/// [allow](RepositoryPolicy::allow) rule, or there are none, and matches no
+/// [deny](RepositoryPolicy::deny) rule. Requests addressing no repository, such as `/v2/`, are
+/// unaffected.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepositoryPolicy {
+    #[builder(default)]
+    pub allow: Vec<RepositoryRule>,
+    #[builder(default)]
+    pub deny: Vec<RepositoryRule>,
+    /// How denied requests are answered.
+    #[builder(default)]
+    pub denied_response: DeniedResponse,
+}
+
+impl RepositoryPolicy {
+    /// Whether a request addressing the given endpoint may be redirected.
+    ///
+    /// Rules match the percent-decoded repository and tag, as the registry decodes them too, so
+    /// that `internal%2Fsecret` is matched as `internal/secret`.
+    pub fn allows(&self, endpoint: &Endpoint) -> bool {
+        let Some(repository) = endpoint.repository() else {
+            return true;
+        };
+
+        let repository = percent_decode_str(repository).decode_utf8_lossy();
+        let repository = repository.as_ref();
+
+        let tag = match endpoint.reference() {
+            Some(Reference::Tag(tag)) => Some(percent_decode_str(tag).decode_utf8_lossy()),
+            _ => None,
+        };
+        let tag = tag.as_deref();
+
+        let allowed = self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|rule| rule.matches(repository, tag, true));
+
+        allowed
+            && !self
+                .deny
+                .iter()
+                .any(|rule| rule.matches(repository, tag, false))
+    }
+
+    /// The error to reject a denied request for the given repository with.
+    pub fn denial(&self, repository: &str) -> RewriteError {
+        match self.denied_response {
+            DeniedResponse::Denied => RewriteError::RepositoryDenied(repository.into()),
+            DeniedResponse::NameUnknown => RewriteError::RepositoryUnknown(repository.into()),
+        }
+    }
+}
+
+/// How requests for denied repositories are answered.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeniedResponse {
+    /// A 403 with the OCI error code `DENIED`.
+    #[default]
+    Denied,
+    /// A 404 with the OCI error code `NAME_UNKNOWN`, as if the repository did not exist.
+    NameUnknown,
+}
+
+impl DeniedResponse {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Denied => "denied",
+            Self::NameUnknown => "name-unknown",
+        }
+    }
+}
+
+impl Display for DeniedResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Parses `denied` or `403`, and `name-unknown` or `404`.
+impl FromStr for DeniedResponse {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let response = s.trim().to_ascii_lowercase().replace('_', "-");
+
+        Ok(match response.as_str() {
+            "denied" | "403" => Self::Denied,
+            "name-unknown" | "404" => Self::NameUnknown,
+            _ => {
+                return Err(format!(
+                    "unknown denied response {s:?}; expected denied or name-unknown"
+                ));
+            }
+        })
+    }
+}
+
+/// A pattern over repository names, optionally restricted to tags matching another pattern.
+///
+/// Written as `repository` or `repository:tag` with globs, such as `internal/*` or
+/// `library/ubuntu:*-rc*`, or as `regex:` followed by a regular expression over repository names.
+/// In configuration files, a table of `repository` and `tag` patterns allows a tag regex too.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RuleValue", into = "RuleValue")]
+pub struct RepositoryRule {
+    pub repository: Pattern,
+    pub tag: Option<Pattern>,
+}
+
+impl RepositoryRule {
+    /// Whether the rule matches a repository and the tag a request addresses, if any.
+    ///
+    /// A rule with a tag pattern matches requests which address no tag, such as those for blobs
+    /// or manifests by digest, only if `untagged` is set, as their tag cannot be known.
+    pub fn matches(&self, repository: &str, tag: Option<&str>, untagged: bool) -> bool {
+        self.repository.matches(repository)
+            && match (&self.tag, tag) {
+                (None, _) => true,
+                (Some(pattern), Some(tag)) => pattern.matches(tag),
+                (Some(_), None) => untagged,
+            }
+    }
+}
+
+impl Display for RepositoryRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.tag {
+            Some(tag) => write!(f, "{}:{tag}", self.repository),
+            None => write!(f, "{}", self.repository),
+        }
+    }
+}
+
+impl FromStr for RepositoryRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        // repository names cannot contain colons, so a glob's tag follows the first one
+        let (repository, tag) = match s.split_once(':') {
+            Some((repository, tag)) if !s.starts_with(REGEX_PREFIX) => (repository, Some(tag)),
+            _ => (s, None),
+        };
+
+        Ok(Self {
+            repository: repository.parse()?,
+            tag: tag.map(str::parse).transpose()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RuleValue {
+    Short(String),
+    Table {
+        repository: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
+}
+
+impl TryFrom<RuleValue> for RepositoryRule {
+    type Error = String;
+
+    fn try_from(value: RuleValue) -> Result<Self, Self::Error> {
+        match value {
+            RuleValue::Short(rule) => rule.parse(),
+            RuleValue::Table { repository, tag } => Ok(Self {
+                repository: repository.parse()?,
+                tag: tag.as_deref().map(str::parse).transpose()?,
+            }),
+        }
+    }
+}
+
+impl From<RepositoryRule> for RuleValue {
+    fn from(rule: RepositoryRule) -> Self {
+        Self::Table {
+            repository: rule.repository.to_string(),
+            tag: rule.tag.map(|tag| tag.to_string()),
+        }
+    }
+}
+
+/// A glob, in which `*` matches any characters and `?` matches any one character, or a regular
+/// expression, which must match the whole value.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob_matches(glob.as_bytes(), value.as_bytes()),
+            Self::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Glob(glob) => write!(f, "{glob}"),
+            Self::Regex(regex) => {
+                // strip the anchors added when parsing
+                let regex = regex.as_str();
+                write!(f, "{REGEX_PREFIX}{}", &regex[4..regex.len() - 2])
+            }
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        match s.strip_prefix(REGEX_PREFIX) {
+            Some(regex) => Regex::new(&format!("^(?:{regex})$"))
+                .map(Self::Regex)
+                .map_err(|e| format!("invalid regex {regex:?}: {e}")),
+            None if s.is_empty() => Err("empty pattern".into()),
+            None => Ok(Self::Glob(s.into())),
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Glob(a), Self::Glob(b)) => a == b,
+            (Self::Regex(a), Self::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Pattern {}
+
+fn glob_matches(glob: &[u8], value: &[u8]) -> bool {
+    let (mut g, mut v) = (0, 0);
+    // where to resume after the last `*` if the rest fails to match
+    let mut backtrack = None;
+
+    while v < value.len() {
+        match glob.get(g) {
+            Some(b'*') => {
+                backtrack = Some((g, v));
+                g += 1;
+            }
+            Some(&c) if c == b'?' || c == value[v] => {
+                g += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    g = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == b'*')
+}
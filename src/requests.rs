@@ -1,7 +1,18 @@
 use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayV2httpRequest};
-use aws_lambda_events::http::HeaderMap;
+use aws_lambda_events::http::{HeaderMap, Method};
 use aws_lambda_events::query_map::QueryMap;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Characters to percent-encode in query string keys and values: everything but the RFC 3986
+/// unreserved set.
+const QUERY_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -12,6 +23,33 @@ pub enum ApiGatewayRequestType {
 }
 
 impl ApiGatewayRequestType {
+    /// The kind of event the request arrived in, as recorded in metrics.
+    pub fn event_source(&self) -> &'static str {
+        match self {
+            Self::V1(_) => "apigateway-v1",
+            Self::V2(_) => "apigateway-v2",
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        match &self {
+            Self::V1(req) => &req.http_method,
+            Self::V2(req) => &req.request_context.http.method,
+        }
+    }
+
+    pub fn set_method(&mut self, method: Method) {
+        match self {
+            Self::V1(req) => req.http_method = method,
+            Self::V2(req) => req.request_context.http.method = method,
+        }
+    }
+
+    /// The path of the request as received from API Gateway, including any stage or base path.
+    ///
+    /// This is the path the client requested, as recorded in logs, access logs, and traces; use
+    /// [registry_path](Self::registry_path) for the path relative to the registry root which
+    /// redirects are built from.
     pub fn path(&self) -> Option<&String> {
         match &self {
             Self::V1(req) => req.path.as_ref(),
@@ -26,6 +64,146 @@ impl ApiGatewayRequestType {
         }
     }
 
+    /// The path of the request relative to the registry root.
+    ///
+    /// V2 requests made against the default `execute-api` endpoint of a named stage include the
+    /// stage in the raw path, whereas V1 requests do not, so it is removed. The given base path,
+    /// such as that of a custom domain base path mapping, is removed from either version.
+    pub fn registry_path(&self, base_path: Option<&str>) -> &str {
+        let mut path = self.path().map(String::as_str).unwrap_or("/");
+
+        if let Self::V2(req) = self {
+            let is_default_endpoint = req
+                .request_context
+                .domain_name
+                .as_ref()
+                .is_none_or(|d| d.contains(".execute-api."));
+
+            if let Some(stage) = req.request_context.stage.as_ref()
+                && stage != "$default"
+                && is_default_endpoint
+            {
+                path = strip_path_prefix(path, stage);
+            }
+        }
+
+        if let Some(base_path) = base_path {
+            path = strip_path_prefix(path, base_path);
+        }
+
+        path
+    }
+
+    pub fn stage(&self) -> Option<&String> {
+        match &self {
+            Self::V1(req) => req.request_context.stage.as_ref(),
+            Self::V2(req) => req.request_context.stage.as_ref(),
+        }
+    }
+
+    pub fn set_stage(&mut self, stage: impl Into<String>) {
+        match self {
+            Self::V1(req) => req.request_context.stage = Some(stage.into()),
+            Self::V2(req) => req.request_context.stage = Some(stage.into()),
+        }
+    }
+
+    pub fn stage_variables(&self) -> &HashMap<String, String> {
+        match &self {
+            Self::V1(req) => &req.stage_variables,
+            Self::V2(req) => &req.stage_variables,
+        }
+    }
+
+    pub fn stage_variables_mut(&mut self) -> &mut HashMap<String, String> {
+        match self {
+            Self::V1(req) => &mut req.stage_variables,
+            Self::V2(req) => &mut req.stage_variables,
+        }
+    }
+
+    pub fn domain_name(&self) -> Option<&String> {
+        match &self {
+            Self::V1(req) => req.request_context.domain_name.as_ref(),
+            Self::V2(req) => req.request_context.domain_name.as_ref(),
+        }
+    }
+
+    /// The `requestContext.requestId` assigned by API Gateway.
+    pub fn api_request_id(&self) -> Option<&str> {
+        match self {
+            Self::V1(req) => req.request_context.request_id.as_deref(),
+            Self::V2(req) => req.request_context.request_id.as_deref(),
+        }
+    }
+
+    /// The host the request was made to: the custom domain name of the API, or else the `Host`
+    /// header.
+    pub fn host(&self) -> Option<&str> {
+        self.domain_name().map(String::as_str).or_else(|| {
+            self.headers()
+                .get("Host")
+                .and_then(|value| value.to_str().ok())
+        })
+    }
+
+    /// The IP address of the client as seen by API Gateway.
+    pub fn source_ip(&self) -> Option<&str> {
+        match self {
+            Self::V1(req) => req.request_context.identity.source_ip.as_deref(),
+            Self::V2(req) => req.request_context.http.source_ip.as_deref(),
+        }
+    }
+
+    /// The address of the client, given the number of trusted proxies in front of API Gateway
+    /// which append the address they received the request from to `X-Forwarded-For`, such as
+    /// CloudFront.
+    ///
+    /// Clients may send any `X-Forwarded-For` they like, so without trusted proxies this is the
+    /// source IP. Otherwise, it is the address appended by the furthest trusted proxy, ignoring
+    /// any to its left, or the source IP if the request did not pass through as many proxies.
+    /// Addresses which are not valid IPs are ignored.
+    pub fn client_ip(&self, trusted_proxies: usize) -> Option<IpAddr> {
+        let source_ip = self.source_ip()?;
+
+        let mut hops = self
+            .headers()
+            .get_all("X-Forwarded-For")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|hop| !hop.is_empty())
+            .collect::<Vec<_>>();
+
+        // API Gateway may have appended the source IP already
+        if hops.last() != Some(&source_ip) {
+            hops.push(source_ip);
+        }
+
+        let hop = hops
+            .len()
+            .checked_sub(trusted_proxies + 1)
+            .map_or(source_ip, |i| hops[i]);
+
+        hop.parse().ok()
+    }
+
+    /// The protocol of the request, such as `HTTP/1.1`.
+    pub fn protocol(&self) -> Option<&str> {
+        match self {
+            Self::V1(req) => req.request_context.protocol.as_deref(),
+            Self::V2(req) => req.request_context.http.protocol.as_deref(),
+        }
+    }
+
+    pub fn set_domain_name(&mut self, domain_name: impl Into<String>) {
+        match self {
+            Self::V1(req) => req.request_context.domain_name = Some(domain_name.into()),
+            Self::V2(req) => req.request_context.domain_name = Some(domain_name.into()),
+        }
+    }
+
     pub fn query(&self) -> &QueryMap {
         match &self {
             Self::V1(req) => &req.query_string_parameters,
@@ -40,6 +218,68 @@ impl ApiGatewayRequestType {
         }
     }
 
+    pub fn multi_value_query(&self) -> Option<&QueryMap> {
+        match &self {
+            Self::V1(req) => Some(&req.multi_value_query_string_parameters),
+            Self::V2(_) => None,
+        }
+    }
+
+    pub fn multi_value_query_mut(&mut self) -> Option<&mut QueryMap> {
+        match self {
+            Self::V1(req) => Some(&mut req.multi_value_query_string_parameters),
+            Self::V2(_) => None,
+        }
+    }
+
+    pub fn raw_query(&self) -> Option<&String> {
+        match &self {
+            Self::V1(_) => None,
+            Self::V2(req) => req.raw_query_string.as_ref(),
+        }
+    }
+
+    pub fn set_raw_query(&mut self, query: impl Into<String>) {
+        if let Self::V2(req) = self {
+            req.raw_query_string = Some(query.into());
+        }
+    }
+
+    /// The query string to forward to the registry, without the leading `?`.
+    ///
+    /// V2 requests carry the query string exactly as the client sent it, so it is passed through
+    /// untouched. V1 requests only carry decoded parameters, so the query string is rebuilt from
+    /// the multi-value parameters to keep repeated keys, falling back to the single-value
+    /// parameters if those are empty.
+    pub fn query_string(&self) -> String {
+        if let Some(raw) = self.raw_query() {
+            return raw.clone();
+        }
+
+        let query = match self.multi_value_query() {
+            Some(query) if !query.is_empty() => query,
+            _ => self.query(),
+        };
+
+        // keys are sorted as the order was already lost in deserialization, values retain theirs
+        let mut keys: Vec<&str> = query.iter().map(|(k, _)| k).collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        keys.into_iter()
+            .flat_map(|k| {
+                query.all(k).unwrap_or_default().into_iter().map(move |v| {
+                    format!(
+                        "{}={}",
+                        utf8_percent_encode(k, QUERY_COMPONENT),
+                        utf8_percent_encode(v, QUERY_COMPONENT)
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
     pub fn headers(&self) -> &HeaderMap {
         match &self {
             Self::V1(req) => &req.multi_value_headers,
@@ -61,3 +301,18 @@ impl ApiGatewayRequestType {
         }
     }
 }
+
+/// Remove a leading path prefix, only matching on whole segments, e.g. `/prod` is removed from
+/// `/prod/v2/` but not from `/production/v2/`.
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> &'a str {
+    let prefix = prefix.trim_matches('/');
+
+    if prefix.is_empty() {
+        return path;
+    }
+
+    match path.trim_start_matches('/').strip_prefix(prefix) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => path,
+    }
+}
@@ -1,7 +1,7 @@
 use crate::requests::ApiGatewayRequestType;
 use aws_lambda_events::apigw::{ApiGatewayProxyResponse, ApiGatewayV2httpResponse};
 use aws_lambda_events::encodings::Body;
-use aws_lambda_events::http::HeaderMap;
+use aws_lambda_events::http::{HeaderMap, HeaderValue};
 use bon::Builder;
 use serde::{Deserialize, Serialize};
 
@@ -63,6 +63,20 @@ impl ApiGatewayResponseType {
         }
     }
 
+    /// Insert a header, into the multi-value headers as well as the single-value ones.
+    pub fn insert_header(&mut self, name: &'static str, value: HeaderValue) {
+        match self {
+            Self::V1(resp) => {
+                resp.multi_value_headers.insert(name, value.clone());
+                resp.headers.insert(name, value);
+            }
+            Self::V2(resp) => {
+                resp.multi_value_headers.insert(name, value.clone());
+                resp.headers.insert(name, value);
+            }
+        }
+    }
+
     pub fn status_code(&self) -> i64 {
         match self {
             Self::V1(resp) => resp.status_code,
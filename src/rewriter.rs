@@ -0,0 +1,691 @@
+use crate::access::AccessLogEntry;
+use crate::analytics::PullAnalytics;
+use crate::config::{
+    CONFIG_FILE_ENV_VAR, CONFIG_RELOAD_TTL_ENV_VAR, Config, ConfigError, StageConfigs,
+};
+use crate::correlation::RequestIds;
+use crate::error::RewriteError;
+use crate::logging::{Level, LogEntry, LogLimiter, Logger};
+use crate::metrics::{RequestMetrics, UNKNOWN_EVENT_SOURCE};
+use crate::paths::normalize_path;
+use crate::registry::Endpoint;
+use crate::requests::ApiGatewayRequestType;
+use crate::responses::ApiGatewayResponseType;
+use crate::source::{FileConfigSource, ReloadingConfig, WithEnvOverrides};
+use crate::telemetry::{Telemetry, Trace};
+use crate::templates::ErrorContext;
+use crate::{
+    create_event_error_response, create_method_not_allowed_response, create_redirect_response,
+    create_rewrite_error_response, insert_request_id_headers, location_url,
+};
+use aws_lambda_events::http::{HeaderMap, HeaderValue};
+use lambda_runtime::Context;
+use opentelemetry::KeyValue;
+use serde::Deserialize;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// What to do with a request, as decided by [Rewriter::route].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Decision {
+    /// Redirect the client to the given URL on the registry, addressing the given endpoint.
+    Redirect {
+        location: String,
+        endpoint: Endpoint,
+    },
+    /// Reject the request with the given error.
+    Reject(RewriteError),
+}
+
+impl Decision {
+    /// The name of the decision in log lines: `redirect` or `reject`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Redirect { .. } => "redirect",
+            Self::Reject(_) => "reject",
+        }
+    }
+}
+
+/// The result of responding to a request, as recorded in logs and metrics.
+struct Outcome {
+    decision: Decision,
+    /// The registry endpoint the request addressed, if its path is valid.
+    endpoint: Option<Endpoint>,
+    /// The host of the registry the request was redirected to.
+    registry: Option<String>,
+    resp: ApiGatewayResponseType,
+}
+
+/// When a request was received, and how long it took to handle.
+#[derive(Debug, Clone, Copy)]
+struct Timing {
+    received: SystemTime,
+    latency: Duration,
+}
+
+/// Rewrites API Gateway requests into redirects to an ECR registry according to its [Config].
+///
+/// A rewriter holds no global state, so differently configured instances may be used side by
+/// side.
+#[derive(Debug, Clone, Default)]
+pub struct Rewriter {
+    config: ConfigHandle,
+    stages: Arc<StageConfigs>,
+    limiter: Arc<LogLimiter>,
+    analytics: Arc<PullAnalytics>,
+    telemetry: Arc<Telemetry>,
+}
+
+#[derive(Debug, Clone)]
+enum ConfigHandle {
+    Static(Arc<Config>),
+    Reloading(Arc<ReloadingConfig>),
+}
+
+impl Default for ConfigHandle {
+    fn default() -> Self {
+        Self::Static(Default::default())
+    }
+}
+
+impl Rewriter {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config: ConfigHandle::Static(Arc::new(config)),
+            stages: Default::default(),
+            limiter: Default::default(),
+            analytics: Default::default(),
+            telemetry: Default::default(),
+        }
+    }
+
+    /// Create a rewriter whose configuration is periodically reloaded.
+    pub fn reloading(config: ReloadingConfig) -> Self {
+        Self {
+            config: ConfigHandle::Reloading(Arc::new(config)),
+            stages: Default::default(),
+            limiter: Default::default(),
+            analytics: Default::default(),
+            telemetry: Default::default(),
+        }
+    }
+
+    /// Create a rewriter configured from the environment as per [Config::from_env].
+    ///
+    /// If [CONFIG_RELOAD_TTL_ENV_VAR] is set along with [CONFIG_FILE_ENV_VAR], the configuration
+    /// file is reloaded at that interval.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let (Ok(path), Ok(ttl)) = (
+            env::var(CONFIG_FILE_ENV_VAR),
+            env::var(CONFIG_RELOAD_TTL_ENV_VAR),
+        ) else {
+            return Ok(Self::new(Config::from_env()?));
+        };
+
+        let ttl = ttl
+            .trim()
+            .parse()
+            .map(Duration::from_secs)
+            .map_err(|e| ConfigError::invalid(CONFIG_RELOAD_TTL_ENV_VAR, e))?;
+
+        Ok(Self::reloading(ReloadingConfig::new(
+            WithEnvOverrides(FileConfigSource::new(path)),
+            ttl,
+        )?))
+    }
+
+    /// The current configuration.
+    pub fn config(&self) -> Arc<Config> {
+        match &self.config {
+            ConfigHandle::Static(config) => config.clone(),
+            ConfigHandle::Reloading(config) => config.current(),
+        }
+    }
+
+    /// The configuration to use for a request, derived from the given one with the request's
+    /// stage variables, logging a limited number of warnings for invalid ones.
+    fn config_for(&self, config: &Arc<Config>, req: &ApiGatewayRequestType) -> Arc<Config> {
+        let (derived, errors) = self.stages.get(config, req);
+
+        for e in errors.iter() {
+            config.logger().log_limited(
+                &self.limiter,
+                "stage-variables",
+                &config.log_limit,
+                Level::Warn,
+                || format!("Ignoring invalid stage variable: {e}"),
+            );
+        }
+
+        derived
+    }
+
+    /// Write a summary of the pulls counted since the previous one, if any, regardless of the
+    /// configured interval, such as when the execution environment is shutting down.
+    pub fn flush_analytics(&self) {
+        if self.config().analytics.enabled {
+            self.analytics.take(SystemTime::now()).write();
+        }
+    }
+
+    /// Wait for the spans queued for export to be sent, such as when the execution environment is
+    /// shutting down.
+    pub fn flush_spans(&self) {
+        if let Err(e) = self.telemetry.flush() {
+            self.config()
+                .logger()
+                .warn(format!("Unable to export spans: {e}"));
+        }
+    }
+
+    /// Decide what to do with a request without building a response.
+    pub fn route(&self, req: &ApiGatewayRequestType) -> Decision {
+        Self::route_with(&self.config_for(&self.config(), req), req)
+    }
+
+    /// Route a request and build the corresponding response.
+    pub fn respond(&self, req: &ApiGatewayRequestType) -> ApiGatewayResponseType {
+        self.respond_with(
+            &self.config(),
+            req,
+            &RequestIds::from_request(req),
+            &Trace::disabled(),
+        )
+        .resp
+    }
+
+    fn route_with(config: &Config, req: &ApiGatewayRequestType) -> Decision {
+        if !config.allowed_methods.allows(req.method()) {
+            return Decision::Reject(RewriteError::MethodNotAllowed(req.method().clone()));
+        }
+
+        let Ok(host) = config.resolved_registry_host() else {
+            return Decision::Reject(RewriteError::Misconfigured);
+        };
+
+        let path = match normalize_path(req.registry_path(config.base_path.as_deref())) {
+            Ok(path) => path,
+            Err(e) => return Decision::Reject(RewriteError::InvalidPath(e)),
+        };
+
+        let endpoint = Endpoint::parse(&path);
+
+        if !config.repositories.allows(&endpoint)
+            && let Some(repository) = endpoint.repository()
+        {
+            return Decision::Reject(config.repositories.denial(repository));
+        }
+
+        let location = location_url(&host, &path, &req.query_string());
+
+        if HeaderValue::from_str(&location).is_err() {
+            return Decision::Reject(RewriteError::InvalidLocation);
+        }
+
+        Decision::Redirect { location, endpoint }
+    }
+
+    fn respond_with(
+        &self,
+        config: &Arc<Config>,
+        req: &ApiGatewayRequestType,
+        ids: &RequestIds,
+        trace: &Trace,
+    ) -> Outcome {
+        let config = self.config_for(config, req);
+        let decision = trace.in_span("route", || Self::route_with(&config, req));
+
+        let resp = trace.in_span("build response", || {
+            let mut resp = self.build_response(&config, req, ids, &decision);
+            insert_request_id_headers(&mut resp, ids);
+            resp
+        });
+
+        Outcome {
+            registry: match &decision {
+                Decision::Redirect { .. } => config.resolved_registry_host().ok().map(Into::into),
+                Decision::Reject(_) => None,
+            },
+            decision,
+            endpoint: Self::endpoint(&config, req),
+            resp,
+        }
+    }
+
+    fn build_response(
+        &self,
+        config: &Config,
+        req: &ApiGatewayRequestType,
+        ids: &RequestIds,
+        decision: &Decision,
+    ) -> ApiGatewayResponseType {
+        match decision {
+            Decision::Redirect { location, endpoint } => create_redirect_response(
+                req,
+                location.clone(),
+                &config.cache.directives(endpoint, config.cache_max_age),
+            ),
+            Decision::Reject(RewriteError::MethodNotAllowed(_)) => {
+                create_method_not_allowed_response(
+                    req,
+                    &config.allowed_methods,
+                    &Self::error_context(config, req, ids),
+                )
+            }
+            Decision::Reject(e) => {
+                if let (RewriteError::Misconfigured, Err(cause)) =
+                    (e, config.resolved_registry_host())
+                {
+                    self.log_error(
+                        config,
+                        e.code(),
+                        LogEntry::builder()
+                            .message(format!("Misconfiguration; {cause}"))
+                            .ids(ids.clone())
+                            .code(e.code())
+                            .build(),
+                    );
+                }
+
+                create_rewrite_error_response(req, e, &Self::error_context(config, req, ids))
+            }
+        }
+    }
+
+    /// The registry endpoint a request addresses, if its path is valid.
+    fn endpoint(config: &Config, req: &ApiGatewayRequestType) -> Option<Endpoint> {
+        normalize_path(req.registry_path(config.base_path.as_deref()))
+            .ok()
+            .map(|path| Endpoint::parse(&path))
+    }
+
+    /// Describe a request for rendering an error response to it with the configured branding.
+    fn error_context(
+        config: &Config,
+        req: &ApiGatewayRequestType,
+        ids: &RequestIds,
+    ) -> ErrorContext {
+        let host = req.host();
+
+        ErrorContext {
+            ids: ids.clone(),
+            host: host.map(Into::into),
+            image: Self::endpoint(config, req).and_then(|endpoint| endpoint.image()),
+            branding: config.error_pages.branding(host),
+        }
+    }
+
+    /// Take a raw API Gateway proxy event and rewrite it into an API Gateway proxy response
+    /// containing the redirect or an error message if no host is defined.
+    ///
+    /// Responses and log lines carry the Lambda request ID from the context, along with the API
+    /// Gateway request ID and X-Ray trace ID. This never panics: a panic while handling the event
+    /// is logged and answered with a 500, in the version of the request if it is one. With
+    /// [install_panic_hook] the panic is only logged here, as one JSON line with its location,
+    /// rather than also by the default hook.
+    ///
+    /// If an OTLP endpoint is configured, the handling of the event is traced and exported to it,
+    /// continuing the trace of the W3C `traceparent` header of the request, if any.
+    pub fn rewrite(&self, req: serde_json::Value, ctx: Context) -> ApiGatewayResponseType {
+        HANDLING.set(true);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.handle(&req, &ctx)));
+        HANDLING.set(false);
+
+        result.unwrap_or_else(|panic| match ApiGatewayRequestType::deserialize(&req) {
+            // answer in the version of the request
+            Ok(req) => {
+                let ids = RequestIds::new(&ctx, Some(&req));
+                self.panicked(&req, &ids, panic.as_ref())
+            }
+            Err(_) => {
+                let ids = RequestIds::new(&ctx, None);
+
+                self.log_error(
+                    &Config::default(),
+                    RewriteError::Internal.code(),
+                    panic_entry(panic.as_ref(), ids.clone()),
+                );
+                create_event_error_response(&RewriteError::Internal, &ids)
+            }
+        })
+    }
+
+    fn handle(&self, event: &serde_json::Value, ctx: &Context) -> ApiGatewayResponseType {
+        let start = Instant::now();
+        let received = SystemTime::now();
+
+        // use the same configuration throughout, even if it is reloaded concurrently
+        let config = self.config();
+
+        // try to get the request as either v1 or v2 of api gateway
+        let req = match ApiGatewayRequestType::deserialize(event) {
+            Ok(req) => req,
+            Err(e) => {
+                let ids = RequestIds::new(ctx, None);
+                let error = RewriteError::InvalidEvent;
+
+                let trace = self
+                    .telemetry
+                    .start(&config.tracing, &HeaderMap::new(), received);
+                trace.record("parse event", received, SystemTime::now());
+
+                self.log_error(
+                    &config,
+                    error.code(),
+                    LogEntry::builder()
+                        .message(format!(
+                            "Unable to deserialize event as either version of API gateway request: {e}"
+                        ))
+                        .ids(ids.clone())
+                        .code(error.code())
+                        .status(error.status().as_u16())
+                        .latency_ms(start.elapsed())
+                        .event(config.redaction.redacted(event))
+                        .build(),
+                );
+
+                // here we cannot determine what kind of response to issue so we return a v1
+                let outcome = Outcome {
+                    resp: create_event_error_response(&error, &ids),
+                    decision: Decision::Reject(error),
+                    endpoint: None,
+                    registry: None,
+                };
+
+                let timing = Timing {
+                    received,
+                    latency: start.elapsed(),
+                };
+
+                self.observe(&config, None, &ids, &outcome, timing, trace);
+
+                return outcome.resp;
+            }
+        };
+
+        let ids = RequestIds::new(ctx, Some(&req));
+
+        let trace = self
+            .telemetry
+            .start(&config.tracing, req.headers(), received);
+        trace.record("parse event", received, SystemTime::now());
+
+        // sampled requests and those carrying the debug secret are logged in more detail
+        let logger = config.request_logger(&req);
+
+        // dump the event if debug logging is enabled
+        logger.log(Level::Debug, || {
+            LogEntry::builder()
+                .message("Event payload")
+                .ids(ids.clone())
+                .event(config.redaction.redacted(event))
+                .build()
+        });
+
+        let outcome = self.respond_with(&config, &req, &ids, &trace);
+
+        if let Decision::Redirect { location, endpoint } = &outcome.decision {
+            logger.log(Level::Trace, || {
+                LogEntry::builder()
+                    .message(format!(
+                        "Routed request to the {} endpoint",
+                        endpoint.kind().as_str()
+                    ))
+                    .ids(ids.clone())
+                    .location(config.redaction.redacted_url(location))
+                    .build()
+            });
+        }
+
+        logger.log(Level::Debug, || {
+            LogEntry::builder()
+                .message("Response payload")
+                .ids(ids.clone())
+                .maybe_response(
+                    serde_json::to_value(&outcome.resp)
+                        .ok()
+                        .map(|resp| config.redaction.redacted(&resp)),
+                )
+                .build()
+        });
+
+        let timing = Timing {
+            received,
+            latency: start.elapsed(),
+        };
+
+        logger.info(
+            LogEntry::builder()
+                .message("Handled request")
+                .ids(ids.clone())
+                .maybe_host(req.host())
+                .maybe_path(req.path())
+                .decision(outcome.decision.name())
+                .maybe_code(match &outcome.decision {
+                    Decision::Reject(e) => Some(e.code()),
+                    Decision::Redirect { .. } => None,
+                })
+                .maybe_status(u16::try_from(outcome.resp.status_code()).ok())
+                .latency_ms(timing.latency)
+                .build(),
+        );
+
+        self.observe(&config, Some(&req), &ids, &outcome, timing, trace);
+
+        outcome.resp
+    }
+
+    /// Log a panic while handling a request, and answer with an internal error.
+    fn panicked(
+        &self,
+        req: &ApiGatewayRequestType,
+        ids: &RequestIds,
+        panic: &(dyn Any + Send),
+    ) -> ApiGatewayResponseType {
+        // the configuration may be what panicked, and panics are budgeted per client, which may be
+        // what triggers them
+        self.log_error(
+            &Config::default(),
+            &format!(
+                "{}:{}",
+                RewriteError::Internal.code(),
+                req.source_ip().unwrap_or("unknown")
+            ),
+            panic_entry(panic, ids.clone()),
+        );
+
+        // avoid anything configurable, which may be what panicked
+        let context = ErrorContext {
+            ids: ids.clone(),
+            ..Default::default()
+        };
+
+        let mut resp = create_rewrite_error_response(req, &RewriteError::Internal, &context);
+        insert_request_id_headers(&mut resp, ids);
+
+        resp
+    }
+
+    /// Record how a request, or an event which is not one, was handled in metrics, the access log,
+    /// pull analytics, and its trace, as configured.
+    fn observe(
+        &self,
+        config: &Config,
+        req: Option<&ApiGatewayRequestType>,
+        ids: &RequestIds,
+        outcome: &Outcome,
+        timing: Timing,
+        trace: Trace,
+    ) {
+        let Outcome {
+            decision,
+            endpoint,
+            registry,
+            resp,
+        } = outcome;
+
+        let status = u16::try_from(resp.status_code()).ok();
+        let redirect = match decision {
+            Decision::Redirect { endpoint, .. } => Some(endpoint),
+            Decision::Reject(_) => None,
+        };
+
+        config.metrics.emit(
+            &RequestMetrics::builder()
+                .event_source(req.map_or(UNKNOWN_EVENT_SOURCE, |req| req.event_source()))
+                .maybe_host(req.and_then(|req| req.host()))
+                .maybe_repository(endpoint.as_ref().and_then(Endpoint::repository))
+                .maybe_endpoint(endpoint.as_ref().map(|e| e.kind().as_str()))
+                .status(status.unwrap_or_default())
+                .redirected(redirect.is_some())
+                .allow_listed(!config.repositories.allow.is_empty())
+                .config_error(*decision == Decision::Reject(RewriteError::Misconfigured))
+                .latency(timing.latency)
+                .build(),
+        );
+
+        if let Some(req) = req {
+            config.access_log.write(|| {
+                AccessLogEntry::new(
+                    req,
+                    resp,
+                    registry.as_deref(),
+                    ids,
+                    config.trusted_proxies,
+                    timing.received,
+                    timing.latency,
+                )
+            });
+        }
+
+        if config.analytics.enabled {
+            if let (Some(req), Some(endpoint)) = (req, redirect) {
+                self.analytics.record(req.method(), endpoint);
+            }
+
+            let interval = Duration::from_secs(config.analytics.interval);
+
+            if let Some(summary) = self.analytics.take_if_due(interval, SystemTime::now()) {
+                summary.write();
+            }
+        }
+
+        if trace.is_enabled() {
+            let attributes = [
+                ids.request_id
+                    .clone()
+                    .map(|id| KeyValue::new("faas.invocation_id", id)),
+                Some(KeyValue::new("faas.trigger", "http")),
+                req.map(|req| KeyValue::new("http.request.method", req.method().to_string())),
+                req.and_then(|req| req.path())
+                    .map(|path| KeyValue::new("url.path", path.to_string())),
+                req.and_then(|req| req.host())
+                    .map(|host| KeyValue::new("server.address", host.to_string())),
+                status.map(|status| KeyValue::new("http.response.status_code", i64::from(status))),
+                Some(KeyValue::new(
+                    "lambda_ecr_rewrite.decision",
+                    decision.name(),
+                )),
+                endpoint
+                    .as_ref()
+                    .map(|e| KeyValue::new("lambda_ecr_rewrite.endpoint", e.kind().as_str())),
+                registry
+                    .clone()
+                    .map(|registry| KeyValue::new("lambda_ecr_rewrite.registry", registry)),
+                match decision {
+                    Decision::Reject(e) => Some(KeyValue::new("error.type", e.code())),
+                    Decision::Redirect { .. } => None,
+                },
+            ];
+
+            // events which are not requests are errors in the integration with API Gateway
+            let error = req.is_none() || status.is_none_or(|status| status >= 500);
+            let name = req.map_or("event", |req| req.method().as_str());
+
+            trace.finish(name, attributes.into_iter().flatten().collect(), error);
+            self.log_export_error(config);
+        }
+    }
+
+    /// Log a limited number of warnings for spans which could not be exported.
+    fn log_export_error(&self, config: &Config) {
+        if let Some(e) = self.telemetry.take_error() {
+            config.logger().log_limited(
+                &self.limiter,
+                "telemetry",
+                &config.log_limit,
+                Level::Warn,
+                || format!("Unable to export spans: {e}"),
+            );
+        }
+    }
+
+    /// Log an error, limited to the configured budget of lines with the same key.
+    fn log_error(&self, config: &Config, key: &str, entry: LogEntry) {
+        config
+            .logger()
+            .log_limited(&self.limiter, key, &config.log_limit, Level::Error, || {
+                entry
+            });
+    }
+}
+
+thread_local! {
+    /// Whether [Rewriter::rewrite] is handling an event on this thread, and so logs any panic.
+    static HANDLING: Cell<bool> = const { Cell::new(false) };
+    /// The description of the last panic while handling an event on this thread, with its
+    /// location, left by the hook of [install_panic_hook] for [Rewriter::rewrite] to log.
+    static PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Replace the default panic hook, which prints the panic to standard error as plain text, with
+/// one logging it as a single JSON line at [Level::Error].
+///
+/// A panic while [Rewriter::rewrite] handles an event is logged there instead, along with the
+/// identifiers of the request.
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        let message = match info.location() {
+            Some(location) => format!("{} at {location}", panic_message(info.payload())),
+            None => panic_message(info.payload()).to_string(),
+        };
+
+        if HANDLING.get() {
+            PANIC.set(Some(message));
+        } else {
+            Logger::default().error(format!("Panic: {message}"));
+        }
+    }));
+}
+
+/// Describe a panic caught while handling a request.
+fn panic_entry(panic: &(dyn Any + Send), ids: RequestIds) -> LogEntry {
+    let message = PANIC
+        .take()
+        .unwrap_or_else(|| panic_message(panic).to_string());
+
+    LogEntry::builder()
+        .message(format!("Panic while handling request: {message}"))
+        .ids(ids)
+        .code(RewriteError::Internal.code())
+        .build()
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(unknown panic payload)")
+}
+
+impl From<Config> for Rewriter {
+    fn from(config: Config) -> Self {
+        Self::new(config)
+    }
+}
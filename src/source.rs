@@ -0,0 +1,170 @@
+use crate::config::{Config, ConfigError};
+use parking_lot::RwLock;
+use std::env;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A source from which configuration can be fetched, such as a file or a parameter store.
+pub trait ConfigSource: Send + Sync {
+    fn fetch(&self) -> Result<Config, ConfigError>;
+
+    /// Fetch the configuration along with invalid parts of it which are being ignored, leaving it
+    /// to the caller to decide whether to log them.
+    fn fetch_checked(&self) -> Result<(Config, Vec<ConfigError>), ConfigError> {
+        self.fetch().map(|config| (config, vec![]))
+    }
+}
+
+impl<F> ConfigSource for F
+where
+    F: Fn() -> Result<Config, ConfigError> + Send + Sync,
+{
+    fn fetch(&self) -> Result<Config, ConfigError> {
+        self()
+    }
+}
+
+/// Reads configuration from a TOML, JSON, or YAML file.
+#[derive(Debug, Clone)]
+pub struct FileConfigSource {
+    path: PathBuf,
+}
+
+impl FileConfigSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ConfigSource for FileConfigSource {
+    fn fetch(&self) -> Result<Config, ConfigError> {
+        Config::from_file(&self.path)
+    }
+}
+
+/// Applies environment variable overrides and validation to configuration from another source,
+/// as [Config::from_env] does for the configuration file.
+#[derive(Debug, Clone)]
+pub struct WithEnvOverrides<S>(pub S);
+
+impl<S: ConfigSource> ConfigSource for WithEnvOverrides<S> {
+    fn fetch(&self) -> Result<Config, ConfigError> {
+        self.0.fetch()?.finish(|name| env::var(name).ok())
+    }
+
+    fn fetch_checked(&self) -> Result<(Config, Vec<ConfigError>), ConfigError> {
+        self.0.fetch()?.checked(|name| env::var(name).ok())
+    }
+}
+
+/// Configuration which is periodically re-fetched from a [ConfigSource].
+///
+/// The configuration is refreshed on first use after the TTL has elapsed. If fetching fails, or the
+/// fetched configuration has problems which the current one does not, such as a missing registry
+/// host, the last known good configuration continues to be used until the next attempt, one TTL
+/// later.
+///
+/// Invalid configuration which is ignored is logged when it is first fetched, not on every reload
+/// which fetches the same configuration.
+pub struct ReloadingConfig {
+    source: Box<dyn ConfigSource>,
+    ttl: Duration,
+    /// The current configuration, the problems it was fetched with, and when it was fetched.
+    state: RwLock<State>,
+}
+
+struct State {
+    config: Arc<Config>,
+    errors: Arc<[String]>,
+    fetched: Instant,
+}
+
+impl ReloadingConfig {
+    /// Create reloading configuration, failing if the initial fetch fails as there is no good
+    /// configuration to fall back on.
+    pub fn new(source: impl ConfigSource + 'static, ttl: Duration) -> Result<Self, ConfigError> {
+        let (config, errors) = source.fetch_checked()?;
+        config.warn(&errors);
+
+        Ok(Self {
+            source: Box::new(source),
+            ttl,
+            state: RwLock::new(State {
+                config: Arc::new(config),
+                errors: errors.iter().map(ToString::to_string).collect(),
+                fetched: Instant::now(),
+            }),
+        })
+    }
+
+    /// The current configuration, refreshing it first if the TTL has elapsed.
+    ///
+    /// The source is fetched without holding the lock, so other callers are never blocked on it:
+    /// while one caller refreshes, the others keep using the current configuration.
+    pub fn current(&self) -> Arc<Config> {
+        {
+            let guard = self.state.read();
+
+            if guard.fetched.elapsed() < self.ttl {
+                return guard.config.clone();
+            }
+        }
+
+        let (current, current_errors) = {
+            let mut guard = self.state.write();
+
+            // another caller may have claimed the refresh while we waited for the lock
+            if guard.fetched.elapsed() < self.ttl {
+                return guard.config.clone();
+            }
+
+            guard.fetched = Instant::now();
+            (guard.config.clone(), guard.errors.clone())
+        };
+
+        match self.source.fetch_checked() {
+            Ok((config, _)) if config == *current => current,
+            Ok((_, errors))
+                if errors
+                    .iter()
+                    .any(|e| !current_errors.contains(&e.to_string())) =>
+            {
+                current.logger().warn(format!(
+                    "Reloaded configuration is invalid, keeping last known good configuration: {}",
+                    ConfigError::Multiple(errors)
+                ));
+                current
+            }
+            Ok((config, errors)) => {
+                config.warn(&errors);
+
+                let config = Arc::new(config);
+                let mut guard = self.state.write();
+                guard.config = config.clone();
+                guard.errors = errors.iter().map(ToString::to_string).collect();
+                config
+            }
+            Err(e) => {
+                current.logger().warn(format!(
+                    "Unable to reload configuration, keeping last known good configuration: {e}"
+                ));
+                current
+            }
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+impl Debug for ReloadingConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReloadingConfig")
+            .field("ttl", &self.ttl)
+            .field("config", &self.state.read().config)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,318 @@
+use aws_lambda_events::http::{HeaderMap, Uri};
+use bon::Builder;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::{
+    Span as _, SpanKind, Status, TraceContextExt, Tracer as _, TracerProvider as _,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{ExporterBuildError, Protocol, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider, SpanData};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// The W3C Trace Context header carrying the trace and parent span of a request.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// The service name of exported spans unless one is configured.
+pub const DEFAULT_SERVICE_NAME: &str = "lambda-ecr-rewrite";
+
+/// The path of the OTLP/HTTP traces endpoint, appended to a base endpoint.
+pub const OTLP_TRACES_PATH: &str = "/v1/traces";
+
+/// Whether and where to export spans.
+#[derive(Debug, Clone, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracingConfig {
+    /// The `http://` or `https://` URL of an OTLP/HTTP traces endpoint, such as that of a local
+    /// collector or the ADOT Lambda layer at `http://localhost:4318/v1/traces`. Spans are only
+    /// recorded if set.
+    #[builder(into)]
+    pub endpoint: Option<String>,
+    /// The `service.name` of exported spans.
+    #[builder(into, default = DEFAULT_SERVICE_NAME)]
+    pub service_name: String,
+    /// How long to wait for the endpoint, in milliseconds.
+    #[builder(default = 500)]
+    pub timeout: u64,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl TracingConfig {
+    /// The endpoint, if it is not one that spans can be exported to.
+    pub(crate) fn invalid_endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref().filter(|endpoint| {
+            endpoint.parse::<Uri>().map_or(true, |uri| {
+                !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none()
+            })
+        })
+    }
+
+    /// Build a provider exporting spans to the endpoint in batches, from a background thread.
+    fn provider(&self, error: &Arc<Mutex<Option<ExportError>>>) -> Option<SdkTracerProvider> {
+        let endpoint = self.endpoint.as_deref()?;
+
+        let exporter = match opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpJson)
+            .with_endpoint(endpoint)
+            .with_timeout(Duration::from_millis(self.timeout))
+            .build()
+        {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                *error.lock() = Some(ExportError::Build(e));
+                return None;
+            }
+        };
+
+        let resource = Resource::builder_empty()
+            .with_service_name(self.service_name.clone())
+            .with_attributes([
+                KeyValue::new("cloud.provider", "aws"),
+                KeyValue::new("cloud.platform", "aws_lambda"),
+            ])
+            .build();
+
+        // the default sampler follows the sampled flag of the incoming traceparent, if any
+        Some(
+            SdkTracerProvider::builder()
+                .with_resource(resource)
+                .with_batch_exporter(ReportingExporter {
+                    inner: exporter,
+                    error: error.clone(),
+                })
+                .build(),
+        )
+    }
+}
+
+/// Traces requests and exports their spans to the endpoint of the given configuration.
+///
+/// Spans are queued and sent in batches by the [BatchSpanProcessor] of the OpenTelemetry SDK, from
+/// a background thread, so that responses never wait for the endpoint. On Lambda, a batch may only
+/// be sent during a later invocation, as the execution environment is frozen between them;
+/// [flush](Telemetry::flush) sends those still queued, such as when the environment is shutting
+/// down. The provider is built by the first trace, and rebuilt if the configuration changes.
+///
+/// [BatchSpanProcessor]: opentelemetry_sdk::trace::BatchSpanProcessor
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    provider: Mutex<Option<(TracingConfig, Option<SdkTracerProvider>)>>,
+    error: Arc<Mutex<Option<ExportError>>>,
+}
+
+impl Telemetry {
+    /// Start a trace of a request, continuing the trace of the W3C `traceparent` header among the
+    /// given ones if it is valid.
+    ///
+    /// The trace records nothing unless an endpoint is configured, and its spans are only exported
+    /// if the incoming `traceparent`, if any, is sampled.
+    pub fn start(&self, config: &TracingConfig, headers: &HeaderMap, start: SystemTime) -> Trace {
+        let Some(tracer) = self.tracer(config) else {
+            return Trace::disabled();
+        };
+
+        let parent = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+
+        let span = tracer
+            .span_builder("request")
+            .with_kind(SpanKind::Server)
+            .with_start_time(start)
+            .start_with_context(&tracer, &parent);
+
+        Trace {
+            tracer: Some(tracer),
+            context: parent.with_span(span),
+        }
+    }
+
+    /// Wait for the spans queued so far to be sent, up to the timeout of the SDK.
+    pub fn flush(&self) -> Result<(), ExportError> {
+        match &*self.provider.lock() {
+            Some((_, Some(provider))) => provider.force_flush().map_err(ExportError::Export),
+            _ => Ok(()),
+        }
+    }
+
+    /// Take the problem exporting spans since the last call, if any, to be logged.
+    pub fn take_error(&self) -> Option<ExportError> {
+        self.error.lock().take()
+    }
+
+    fn tracer(&self, config: &TracingConfig) -> Option<SdkTracer> {
+        config.endpoint.as_ref()?;
+
+        let mut current = self.provider.lock();
+
+        // build once per configuration, even if building fails
+        if current.as_ref().is_none_or(|(built, _)| built != config) {
+            if let Some((previous, Some(provider))) = current.take() {
+                // send what the previous provider still queued before replacing it
+                let _ = provider.shutdown_with_timeout(Duration::from_millis(previous.timeout));
+            }
+
+            *current = Some((config.clone(), config.provider(&self.error)));
+        }
+
+        current
+            .as_ref()
+            .and_then(|(_, provider)| provider.as_ref())
+            .map(|provider| provider.tracer(env!("CARGO_PKG_NAME")))
+    }
+}
+
+/// The spans of a request, all children of the server span of the request.
+#[derive(Debug)]
+pub struct Trace {
+    tracer: Option<SdkTracer>,
+    /// The context of the server span of the request.
+    context: Context,
+}
+
+impl Trace {
+    /// A trace which records nothing.
+    pub fn disabled() -> Self {
+        Self {
+            tracer: None,
+            context: Context::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tracer.is_some()
+    }
+
+    /// The `traceparent` identifying the server span of the request, to propagate downstream,
+    /// carrying the sampled flag of the incoming one.
+    pub fn traceparent(&self) -> Option<String> {
+        self.tracer.as_ref()?;
+
+        let mut headers = HashMap::new();
+        TraceContextPropagator::new().inject_context(&self.context, &mut headers);
+
+        headers.remove(TRACEPARENT_HEADER)
+    }
+
+    /// Record an operation which has already ended.
+    pub fn record(&self, name: &str, start: SystemTime, end: SystemTime) {
+        if let Some(tracer) = &self.tracer {
+            tracer
+                .span_builder(name.to_string())
+                .with_kind(SpanKind::Internal)
+                .with_start_time(start)
+                .start_with_context(tracer, &self.context)
+                .end_with_timestamp(end);
+        }
+    }
+
+    /// Run an operation in a span.
+    pub fn in_span<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start = SystemTime::now();
+        let result = f();
+
+        self.record(name, start, SystemTime::now());
+
+        result
+    }
+
+    /// End the server span of the request, queueing the spans of the trace for export if it is
+    /// sampled.
+    pub fn finish(self, name: &str, attributes: Vec<KeyValue>, error: bool) {
+        if self.tracer.is_none() {
+            return;
+        }
+
+        let span = self.context.span();
+
+        span.update_name(name.to_string());
+        span.set_attributes(attributes);
+
+        if error {
+            span.set_status(Status::error(""));
+        }
+
+        span.end();
+    }
+}
+
+/// Reads trace context from the headers of a request.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Keeps the last error of the OTLP exporter, which the batch processor otherwise only reports to
+/// the SDK's internal logs, so that it can be logged.
+#[derive(Debug)]
+struct ReportingExporter {
+    inner: opentelemetry_otlp::SpanExporter,
+    error: Arc<Mutex<Option<ExportError>>>,
+}
+
+impl opentelemetry_sdk::trace::SpanExporter for ReportingExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        self.inner.export(batch).await.map_err(|e| {
+            let failure = OTelSdkError::InternalFailure(e.to_string());
+            *self.error.lock() = Some(ExportError::Export(e));
+            failure
+        })
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Reasons spans could not be exported.
+#[derive(Debug)]
+pub enum ExportError {
+    /// The exporter could not be built from the configuration.
+    Build(ExporterBuildError),
+    /// The endpoint could not be reached or rejected the spans.
+    Export(OTelSdkError),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Build(e) => write!(f, "unable to build the exporter: {e}"),
+            Self::Export(e) => write!(f, "unable to send spans: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Build(e) => Some(e),
+            Self::Export(e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use crate::correlation::RequestIds;
+use crate::error::RewriteError;
+use bon::Builder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The HTML error page used unless one is configured.
+pub const DEFAULT_HTML_TEMPLATE: &str = r#"<!doctype html>
+<html lang="en-us">
+  <head>
+    <title>Error: {{status}} ({{reason}})</title>
+  </head>
+  <body>
+    <h1>Error: {{status}} ({{reason}})</h1>
+    <p>{{message}}</p>
+    <p><small>Request ID: {{request_id}}</small></p>
+  </body>
+</html>"#;
+
+/// The values which may be substituted into an error template as `{{name}}`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Placeholder {
+    /// The HTTP status code, such as `404`.
+    Status,
+    /// The reason phrase of the status, such as `Not Found`.
+    Reason,
+    /// The stable [code](RewriteError::code) of the error.
+    Code,
+    /// The short summary of the error.
+    Title,
+    /// The explanation of the error.
+    Message,
+    /// The Lambda request ID.
+    RequestId,
+    /// The X-Ray trace ID.
+    TraceId,
+    /// The vanity domain the request was made to.
+    Host,
+    /// The requested image, such as `library/ubuntu:latest`.
+    Image,
+    /// The configured support link.
+    SupportLink,
+}
+
+impl Placeholder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Reason => "reason",
+            Self::Code => "code",
+            Self::Title => "title",
+            Self::Message => "message",
+            Self::RequestId => "request_id",
+            Self::TraceId => "trace_id",
+            Self::Host => "host",
+            Self::Image => "image",
+            Self::SupportLink => "support_link",
+        }
+    }
+}
+
+impl FromStr for Placeholder {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "status" => Self::Status,
+            "reason" => Self::Reason,
+            "code" => Self::Code,
+            "title" => Self::Title,
+            "message" => Self::Message,
+            "request_id" => Self::RequestId,
+            "trace_id" => Self::TraceId,
+            "host" => Self::Host,
+            "image" => Self::Image,
+            "support_link" => Self::SupportLink,
+            _ => return Err(TemplateError::UnknownPlaceholder(s.into())),
+        })
+    }
+}
+
+/// Reasons a template cannot be parsed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TemplateError {
+    /// A `{{` without a matching `}}`.
+    Unterminated,
+    UnknownPlaceholder(String),
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unterminated => write!(f, "unterminated placeholder, expected \"}}}}\""),
+            Self::UnknownPlaceholder(name) => write!(f, "unknown placeholder {name:?}"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// An error page template in which `{{name}}` is replaced by the value of a [Placeholder].
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(source: impl Into<String>) -> Result<Self, TemplateError> {
+        let source = source.into();
+        let mut parts = vec![];
+        let mut rest = source.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].into()));
+            }
+
+            let Some(end) = rest[start..].find("}}") else {
+                return Err(TemplateError::Unterminated);
+            };
+
+            parts.push(Part::Placeholder(
+                rest[start + 2..start + end].trim().parse()?,
+            ));
+
+            rest = &rest[start + end + 2..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.into()));
+        }
+
+        Ok(Self { source, parts })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Render the template, passing each substituted value through `escape`.
+    pub fn render(&self, values: &TemplateValues, escape: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::with_capacity(self.source.len());
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => rendered.push_str(literal),
+                Part::Placeholder(placeholder) => {
+                    rendered.push_str(&escape(&values.get(*placeholder)))
+                }
+            }
+        }
+
+        rendered
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(value)
+    }
+}
+
+impl From<Template> for String {
+    fn from(value: Template) -> Self {
+        value.source
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// The values substituted into a template for a particular error.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TemplateValues {
+    pub status: u16,
+    pub reason: String,
+    pub code: String,
+    pub title: String,
+    pub message: String,
+    pub request_id: String,
+    pub trace_id: String,
+    pub host: String,
+    pub image: String,
+    pub support_link: String,
+}
+
+impl TemplateValues {
+    pub fn new(error: &RewriteError, context: &ErrorContext) -> Self {
+        let status = error.status();
+
+        Self {
+            status: status.as_u16(),
+            reason: status.canonical_reason().unwrap_or("Error").into(),
+            code: error.code().into(),
+            title: error.title().into(),
+            message: error.to_string(),
+            request_id: context.ids.primary().unwrap_or_default().into(),
+            trace_id: context.ids.trace_id.clone().unwrap_or_default(),
+            host: context.host.clone().unwrap_or_default(),
+            image: context.image.clone().unwrap_or_default(),
+            support_link: context
+                .branding
+                .support_link
+                .clone()
+                .filter(|link| is_valid_support_link(link))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn get(&self, placeholder: Placeholder) -> String {
+        match placeholder {
+            Placeholder::Status => self.status.to_string(),
+            Placeholder::Reason => self.reason.clone(),
+            Placeholder::Code => self.code.clone(),
+            Placeholder::Title => self.title.clone(),
+            Placeholder::Message => self.message.clone(),
+            Placeholder::RequestId => self.request_id.clone(),
+            Placeholder::TraceId => self.trace_id.clone(),
+            Placeholder::Host => self.host.clone(),
+            Placeholder::Image => self.image.clone(),
+            Placeholder::SupportLink => self.support_link.clone(),
+        }
+    }
+
+    /// Values exercising every placeholder, for validating templates.
+    pub(crate) fn sample() -> Self {
+        Self::new(
+            &RewriteError::Misconfigured,
+            &ErrorContext {
+                ids: RequestIds {
+                    request_id: Some("00000000-0000-0000-0000-000000000000".into()),
+                    api_request_id: Some("AbCdEfGhIjKlMnO=".into()),
+                    trace_id: Some("Root=1-00000000-000000000000000000000000".into()),
+                },
+                host: Some("docker.example.com".into()),
+                image: Some("library/ubuntu:latest".into()),
+                branding: Branding::builder()
+                    .support_link("https://example.com/\"support\"")
+                    .build(),
+            },
+        )
+    }
+}
+
+/// Whether a support link is an http(s) or mailto URL, which is safe to render as a link. Others,
+/// such as `javascript:` URLs, are never rendered.
+pub fn is_valid_support_link(link: &str) -> bool {
+    ["https://", "http://", "mailto:"]
+        .iter()
+        .any(|scheme| link.starts_with(scheme))
+}
+
+/// The look of error responses: templates for HTML and JSON bodies and a support link.
+///
+/// The JSON template replaces the OCI error format for `application/json` responses, so it should
+/// keep its `errors` array for registry clients to display.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Branding {
+    pub html: Option<Template>,
+    pub json: Option<Template>,
+    #[builder(into)]
+    pub support_link: Option<String>,
+}
+
+impl Branding {
+    /// This branding, falling back to another for anything it does not set.
+    pub fn or(&self, fallback: &Branding) -> Branding {
+        Branding {
+            html: self.html.clone().or_else(|| fallback.html.clone()),
+            json: self.json.clone().or_else(|| fallback.json.clone()),
+            support_link: self
+                .support_link
+                .clone()
+                .or_else(|| fallback.support_link.clone()),
+        }
+    }
+}
+
+/// Error response branding, by vanity domain.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Builder, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ErrorPages {
+    /// The branding of all domains, unless overridden.
+    #[builder(default)]
+    pub default: Branding,
+    /// Branding by the host name of the vanity domain, falling back to the default.
+    #[builder(default)]
+    pub domains: BTreeMap<String, Branding>,
+}
+
+impl ErrorPages {
+    /// The branding of error responses for a request to the given host.
+    pub fn branding(&self, host: Option<&str>) -> Branding {
+        // ignore the port and case of the host
+        let host = host.map(|h| h.split(':').next().unwrap_or(h).to_ascii_lowercase());
+
+        host.and_then(|host| {
+            self.domains
+                .iter()
+                .find(|(domain, _)| domain.eq_ignore_ascii_case(&host))
+        })
+        .map(|(_, branding)| branding.or(&self.default))
+        .unwrap_or_else(|| self.default.clone())
+    }
+
+    /// All configured branding, by configuration key, for validation.
+    pub(crate) fn configured(&self) -> impl Iterator<Item = (String, &Branding)> {
+        std::iter::once(("error_pages.default".to_string(), &self.default)).chain(
+            self.domains
+                .iter()
+                .map(|(domain, branding)| (format!("error_pages.domains.{domain:?}"), branding)),
+        )
+    }
+}
+
+/// Everything about a request needed to render an error response, beyond the error itself.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ErrorContext {
+    /// Identifies the occurrence of the error.
+    pub ids: RequestIds,
+    /// The vanity domain the request was made to.
+    pub host: Option<String>,
+    /// The requested image, if the request addressed one.
+    pub image: Option<String>,
+    pub branding: Branding,
+}
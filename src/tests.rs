@@ -1,21 +1,64 @@
 mod fixtures;
+mod tests_access;
+mod tests_analytics;
+mod tests_config;
+mod tests_correlation;
+mod tests_ecr;
+mod tests_extension;
+mod tests_logging;
+mod tests_methods;
+mod tests_metrics;
+mod tests_negotiation;
+mod tests_paths;
+mod tests_redaction;
+mod tests_registry;
+mod tests_repositories;
+mod tests_rewriter;
+mod tests_source;
+mod tests_telemetry;
+mod tests_templates;
 mod tests_v1;
 mod tests_v2;
 
+use crate::correlation::RequestIds;
+use crate::error::RewriteError;
+use crate::paths::PathError;
+use crate::render_html_error;
 use crate::requests::ApiGatewayRequestType;
 use crate::responses::{ApiGatewayGenericResponse, ApiGatewayResponseType};
-use crate::{HTML_ERROR_RESPONSE, JSON_ERROR_RESPONSE, LogStatus, log_infrequently};
 use fixtures::{APIGW_REQ_V1, APIGW_REQ_V1_WITH_VERSION, APIGW_REQ_V2};
 use serde_json::Value;
 
 #[test]
-fn test_valid_error_json() {
-    let _: Value = serde_json::from_str(JSON_ERROR_RESPONSE).unwrap();
+fn test_valid_error_problem() {
+    let problem = RewriteError::InvalidPath(PathError::DotSegment).to_problem(&RequestIds {
+        request_id: Some("abc-123".into()),
+        api_request_id: Some("def-456".into()),
+        trace_id: Some("Root=1-abc".into()),
+    });
+    let value: Value = serde_json::to_value(&problem).unwrap();
+
+    assert_eq!(
+        serde_json::json!({
+            "type": "urn:lambda-ecr-rewrite:problem:invalid-path",
+            "title": "Invalid Path",
+            "status": 400,
+            "detail": "Paths may not contain '.' or '..' segments.",
+            "instance": "abc-123",
+            "trace_id": "Root=1-abc",
+        }),
+        value
+    );
 }
 
 #[test]
 fn test_valid_error_html() {
-    assert!(html_parser::Dom::parse(HTML_ERROR_RESPONSE).is_ok());
+    for error in [
+        RewriteError::Misconfigured,
+        RewriteError::InvalidPath(PathError::ProtocolRelative),
+    ] {
+        assert!(html_parser::Dom::parse(&render_html_error(&error, &Default::default())).is_ok());
+    }
 }
 
 /// Utility: deserialize a string into a request type enum
@@ -98,9 +141,3 @@ fn test_api_gateway_req_resp_mapping() {
         "should create a v2 response from a v2 request"
     );
 }
-
-#[test]
-fn test_log_infrequently() {
-    assert_eq!(LogStatus::Emitted, log_infrequently("log message A"));
-    assert_eq!(LogStatus::Ignored, log_infrequently("log message B"));
-}
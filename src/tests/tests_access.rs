@@ -0,0 +1,167 @@
+use crate::access::{AccessLogEntry, AccessLogFormat};
+use crate::config::{ACCESS_LOG_ENV_VAR, Config, TRUSTED_PROXIES_ENV_VAR};
+use crate::correlation::RequestIds;
+use crate::requests::ApiGatewayRequestType;
+use crate::rewriter::Rewriter;
+use crate::tests::fixtures::{APIGW_REQ_V1, APIGW_REQ_V2};
+use aws_lambda_events::http::{HeaderValue, Method};
+use serde_json::{Value, json};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Utility: describe a pull of the given request through a rewriter to `ecr.myhost.com`, behind
+/// the given number of trusted proxies
+fn entry(mut req: ApiGatewayRequestType, trusted_proxies: usize, secs: u64) -> AccessLogEntry {
+    req.set_method(Method::GET);
+    req.set_path("/v2/library/ubuntu/manifests/latest");
+    *req.query_mut() = Default::default();
+
+    if let Some(query) = req.multi_value_query_mut() {
+        *query = Default::default();
+    }
+
+    let resp =
+        Rewriter::new(Config::builder().registry_host("ecr.myhost.com").build()).respond(&req);
+
+    AccessLogEntry::new(
+        &req,
+        &resp,
+        Some("ecr.myhost.com"),
+        &RequestIds {
+            request_id: Some("lambda-id".into()),
+            ..Default::default()
+        },
+        trusted_proxies,
+        UNIX_EPOCH + Duration::from_secs(secs),
+        Duration::from_micros(1250),
+    )
+}
+
+#[test]
+fn test_access_log_formats() {
+    let mut req: ApiGatewayRequestType = serde_json::from_str(APIGW_REQ_V1).unwrap();
+    req.set_domain_name("docker.example.com");
+
+    // behind CloudFront and the proxy it forwarded to
+    let entry = entry(req, 2, 971_186_136);
+
+    assert_eq!(None, entry.format(AccessLogFormat::Off));
+    assert_eq!(
+        Some(
+            r#"54.240.196.186 - - [10/Oct/2000:13:55:36 +0000] "GET /v2/library/ubuntu/manifests/latest HTTP/1.1" 307 - "docker.example.com" "ecr.myhost.com" 1.250"#
+                .into()
+        ),
+        entry.format(AccessLogFormat::Common)
+    );
+    assert_eq!(
+        Some(
+            r#"54.240.196.186 - - [10/Oct/2000:13:55:36 +0000] "GET /v2/library/ubuntu/manifests/latest HTTP/1.1" 307 - - "PostmanRuntime/2.4.5" "docker.example.com" "ecr.myhost.com" 1.250"#
+                .into()
+        ),
+        entry.format(AccessLogFormat::Combined)
+    );
+    assert_eq!(
+        json!({
+            "time": "2000-10-10T13:55:36.000Z",
+            "client_ip": "54.240.196.186",
+            "method": "GET",
+            "host": "docker.example.com",
+            "path": "/v2/library/ubuntu/manifests/latest",
+            "protocol": "HTTP/1.1",
+            "user_agent": "PostmanRuntime/2.4.5",
+            "referer": null,
+            "status": 307,
+            "bytes": 0,
+            "registry": "ecr.myhost.com",
+            "duration_ms": 1.25,
+            "request_id": "lambda-id",
+        }),
+        serde_json::from_str::<Value>(&entry.format(AccessLogFormat::Json).unwrap()).unwrap()
+    );
+}
+
+#[test]
+fn test_access_log_client_ip() {
+    let mut req: ApiGatewayRequestType = serde_json::from_str(APIGW_REQ_V2).unwrap();
+
+    // without X-Forwarded-For, the source IP is used
+    assert_eq!(
+        Some("1.2.3.4"),
+        entry(req.clone(), 0, 0)
+            .client_ip
+            .map(|ip| ip.to_string())
+            .as_deref()
+    );
+
+    // a proxy in front of API Gateway appended the address of the client to its header
+    req.headers_mut().insert(
+        "X-Forwarded-For",
+        HeaderValue::from_static(" 203.0.113.7 , 10.0.0.1"),
+    );
+    req.headers_mut()
+        .insert("Referer", HeaderValue::from_static("https://ex\"ample.com"));
+
+    let entry = entry(req, 1, 1_709_164_800);
+    let line = entry.format(AccessLogFormat::Combined).unwrap();
+
+    // quotes are escaped, and leap days are dated correctly
+    assert!(
+        line.starts_with("10.0.0.1 - - [29/Feb/2024:00:00:00 +0000] \"GET "),
+        "{line}"
+    );
+    assert!(line.contains(r#" "https://ex\"ample.com" "#), "{line}");
+}
+
+#[test]
+fn test_access_log_hostile_forwarded_for() {
+    let mut req: ApiGatewayRequestType = serde_json::from_str(APIGW_REQ_V2).unwrap();
+    req.headers_mut().insert(
+        "X-Forwarded-For",
+        HeaderValue::from_static(
+            "127.0.0.1 - - [01/Jan/1970:00:00:00 +0000] \"GET /, 198.51.100.1",
+        ),
+    );
+
+    let ip = |trusted_proxies| req.client_ip(trusted_proxies).map(|ip| ip.to_string());
+
+    // the client's own entries are not trusted
+    assert_eq!(Some("1.2.3.4".into()), ip(0));
+    // the entry appended by a trusted proxy is
+    assert_eq!(Some("198.51.100.1".into()), ip(1));
+    // anything further left may have been forged, and is only used if it is a valid IP
+    assert_eq!(None, ip(2));
+    // requests which bypassed the proxies fall back to the source IP
+    assert_eq!(Some("1.2.3.4".into()), ip(3));
+
+    let line = entry(req.clone(), 2, 0)
+        .format(AccessLogFormat::Common)
+        .unwrap();
+    assert!(line.starts_with("- - - ["), "{line}");
+}
+
+#[test]
+fn test_access_log_config() {
+    assert_eq!(AccessLogFormat::Off, Config::default().access_log);
+    assert_eq!(Ok(AccessLogFormat::Combined), "Combined".parse());
+    assert_eq!(Ok(AccessLogFormat::Common), "clf".parse());
+    assert!("apache".parse::<AccessLogFormat>().is_err());
+
+    let mut config = Config::default();
+    let errors = config.apply_overrides(|name| (name == ACCESS_LOG_ENV_VAR).then(|| "json".into()));
+
+    assert!(errors.is_empty());
+    assert_eq!(AccessLogFormat::Json, config.access_log);
+
+    let errors = config.apply_overrides(|name| match name {
+        TRUSTED_PROXIES_ENV_VAR => Some("1".into()),
+        _ => None,
+    });
+
+    assert!(errors.is_empty());
+    assert_eq!(1, config.trusted_proxies);
+    assert_eq!(
+        1,
+        config
+            .apply_overrides(|name| (name == TRUSTED_PROXIES_ENV_VAR).then(|| "-1".into()))
+            .len()
+    );
+}
@@ -0,0 +1,161 @@
+use crate::analytics::{
+    DEFAULT_ANALYTICS_INTERVAL, PULL_ANALYTICS_MAX_IMAGES, PullAnalytics, PullCount,
+};
+use crate::config::{Config, ConfigError, PULL_ANALYTICS_ENV_VAR, PULL_ANALYTICS_INTERVAL_ENV_VAR};
+use crate::registry::{Endpoint, Reference};
+use aws_lambda_events::http::Method;
+use serde_json::json;
+use std::time::{Duration, UNIX_EPOCH};
+
+const DIGEST: &str = "sha256:6c3c624b58dbbcd3c0dd82b4c53f04194d1247c6eebdaab7c610cf7d66709b3b";
+
+#[test]
+fn test_pull_analytics() {
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let analytics = PullAnalytics::new(start);
+
+    for (method, path) in [
+        (Method::GET, "/v2/library/ubuntu/manifests/latest"),
+        (Method::GET, "/v2/library/ubuntu/manifests/latest"),
+        (Method::HEAD, "/v2/library/ubuntu/manifests/latest"),
+        (
+            Method::GET,
+            &format!("/v2/library/ubuntu/manifests/{DIGEST}"),
+        ),
+        (Method::GET, "/v2/library/alpine/manifests/3"),
+        // only manifest pulls and checks are counted
+        (Method::DELETE, "/v2/library/alpine/manifests/3"),
+        (Method::GET, &format!("/v2/library/alpine/blobs/{DIGEST}")),
+        (Method::GET, "/v2/library/alpine/tags/list"),
+    ] {
+        analytics.record(&method, &Endpoint::parse(path));
+    }
+
+    let end = start + Duration::from_secs(60);
+    assert_eq!(None, analytics.take_if_due(Duration::from_secs(61), end));
+
+    let summary = analytics.take_if_due(Duration::from_secs(60), end).unwrap();
+
+    assert_eq!(start, summary.start);
+    assert_eq!(end, summary.end);
+    assert_eq!(0, summary.dropped);
+    assert_eq!(
+        vec![
+            (
+                "library/alpine".to_string(),
+                Reference::Tag("3".into()),
+                PullCount {
+                    pulls: 1,
+                    checks: 0
+                }
+            ),
+            (
+                "library/ubuntu".to_string(),
+                Reference::Tag("latest".into()),
+                PullCount {
+                    pulls: 2,
+                    checks: 1
+                }
+            ),
+            (
+                "library/ubuntu".to_string(),
+                Reference::Digest(DIGEST.into()),
+                PullCount {
+                    pulls: 1,
+                    checks: 0
+                }
+            ),
+        ],
+        summary.images
+    );
+
+    let events = summary.events();
+    assert_eq!(4, events.len());
+
+    assert_eq!(
+        json!({
+            "event": "image_pulls",
+            "period_start": "2023-11-14T22:13:20.000Z",
+            "period_end": "2023-11-14T22:14:20.000Z",
+            "repository": "library/ubuntu",
+            "tag": "latest",
+            "pulls": 2,
+            "checks": 1,
+        }),
+        events[1]
+    );
+    assert_eq!(json!(DIGEST), events[2]["digest"]);
+    assert_eq!(
+        json!({
+            "event": "pull_summary",
+            "period_start": "2023-11-14T22:13:20.000Z",
+            "period_end": "2023-11-14T22:14:20.000Z",
+            "images": 3,
+            "pulls": 4,
+            "checks": 1,
+            "dropped": 0,
+        }),
+        events[3]
+    );
+
+    // the next period starts where the previous one ended
+    let summary = analytics.take(end + Duration::from_secs(1));
+    assert!(summary.is_empty());
+    assert_eq!(end, summary.start);
+}
+
+#[test]
+fn test_pull_analytics_max_images() {
+    let analytics = PullAnalytics::new(UNIX_EPOCH);
+
+    for i in 0..PULL_ANALYTICS_MAX_IMAGES {
+        analytics.record(
+            &Method::GET,
+            &Endpoint::parse(&format!("/v2/repo/manifests/{i}")),
+        );
+    }
+
+    // images already counted are still counted, but new ones are not
+    analytics.record(&Method::GET, &Endpoint::parse("/v2/repo/manifests/0"));
+    analytics.record(&Method::GET, &Endpoint::parse("/v2/repo/manifests/new"));
+
+    let summary = analytics.take(UNIX_EPOCH);
+    assert_eq!(PULL_ANALYTICS_MAX_IMAGES, summary.images.len());
+    assert_eq!(1, summary.dropped);
+    assert_eq!(
+        PULL_ANALYTICS_MAX_IMAGES as u64 + 1,
+        summary
+            .images
+            .iter()
+            .map(|(_, _, count)| count.pulls)
+            .sum::<u64>()
+    );
+
+    analytics.record(&Method::GET, &Endpoint::parse("/v2/repo/manifests/new"));
+    assert_eq!(0, analytics.take(UNIX_EPOCH).dropped);
+}
+
+#[test]
+fn test_analytics_config() {
+    let mut config = Config::default();
+    assert!(!config.analytics.enabled);
+    assert_eq!(DEFAULT_ANALYTICS_INTERVAL, config.analytics.interval);
+
+    let errors = config.apply_overrides(|name| match name {
+        PULL_ANALYTICS_ENV_VAR => Some("yes".into()),
+        PULL_ANALYTICS_INTERVAL_ENV_VAR => Some("60".into()),
+        _ => None,
+    });
+
+    assert!(errors.is_empty());
+    assert!(config.analytics.enabled);
+    assert_eq!(60, config.analytics.interval);
+
+    let errors = config
+        .apply_overrides(|name| (name == PULL_ANALYTICS_INTERVAL_ENV_VAR).then(|| "hourly".into()));
+
+    assert!(matches!(
+        &errors[..],
+        [ConfigError::Invalid { key, .. }] if key == PULL_ANALYTICS_INTERVAL_ENV_VAR
+    ));
+}
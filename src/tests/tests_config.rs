@@ -0,0 +1,425 @@
+use crate::cache::{CacheDirectives, CachePolicy};
+use crate::config::{
+    ALLOWED_METHODS_ENV_VAR, AWS_REGION_ENV_VAR, BASE_PATH_ENV_VAR, CACHE_MAX_AGE_DEFAULT,
+    CACHE_MAX_AGE_ENV_VAR, CACHE_MAX_AGE_MAX, CONFIG_RELOAD_TTL_ENV_VAR, Config, ConfigError,
+    ConfigFormat, DEBUG_ENV_VAR, ECR_DUAL_STACK_ENV_VAR, ECR_FIPS_ENV_VAR, ECR_REGION_ENV_VAR,
+    ECR_REGISTRY_ENV_VAR, LOG_LEVEL_ENV_VAR, STAGE_VARIABLES_ENV_VAR, STRICT_CONFIG_ENV_VAR,
+    StageConfigs,
+};
+use crate::methods::MethodPolicy;
+use crate::requests::ApiGatewayRequestType;
+use aws_lambda_events::http::Method;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Utility: build an environment variable lookup function from key/value pairs
+fn lookup<const N: usize>(vars: [(&str, &str); N]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn test_config_defaults() {
+    let config = Config::default();
+
+    assert_eq!(None, config.registry_host);
+    assert_eq!(CACHE_MAX_AGE_DEFAULT, config.cache_max_age);
+    assert_eq!(MethodPolicy::read_only(), config.allowed_methods);
+    assert_eq!(None, config.base_path);
+    assert!(!config.debug);
+}
+
+#[test]
+fn test_config_parse_formats() {
+    let expected = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .cache_max_age(300)
+        .allowed_methods(MethodPolicy::read_write())
+        .base_path("registry")
+        .build();
+
+    let toml = r#"
+        registry_host = "123456789012.dkr.ecr.us-east-1.amazonaws.com"
+        cache_max_age = 300
+        allowed_methods = "read-write"
+        base_path = "registry"
+    "#;
+
+    let json = r#"{
+        "registry_host": "123456789012.dkr.ecr.us-east-1.amazonaws.com",
+        "cache_max_age": 300,
+        "allowed_methods": ["GET", "HEAD", "POST", "PUT", "PATCH"],
+        "base_path": "registry"
+    }"#;
+
+    let yaml = r#"
+        registry_host: 123456789012.dkr.ecr.us-east-1.amazonaws.com
+        cache_max_age: 300
+        allowed_methods: read-write
+        base_path: registry
+    "#;
+
+    assert_eq!(expected, Config::parse(toml, ConfigFormat::Toml).unwrap());
+    assert_eq!(expected, Config::parse(json, ConfigFormat::Json).unwrap());
+    assert_eq!(expected, Config::parse(yaml, ConfigFormat::Yaml).unwrap());
+}
+
+#[test]
+fn test_config_parse_errors() {
+    assert!(matches!(
+        Config::parse("registry_hots = \"x\"", ConfigFormat::Toml),
+        Err(ConfigError::Parse(None, _))
+    ));
+    assert!(matches!(
+        Config::parse(r#"{"allowed_methods": "GET,("}"#, ConfigFormat::Json),
+        Err(ConfigError::Parse(None, _))
+    ));
+    assert!(matches!(
+        Config::from_file("config.ini"),
+        Err(ConfigError::UnsupportedFormat(_))
+    ));
+    assert!(matches!(
+        Config::from_file("/nonexistent/config.toml"),
+        Err(ConfigError::Io(_, _))
+    ));
+}
+
+#[test]
+fn test_config_overrides() {
+    let mut config = Config::builder()
+        .registry_host("from.file.com")
+        .cache_max_age(300)
+        .build();
+
+    let errors = config.apply_overrides(lookup([
+        (ECR_REGISTRY_ENV_VAR, "from.env.com"),
+        (ALLOWED_METHODS_ENV_VAR, "GET,HEAD,DELETE"),
+        (DEBUG_ENV_VAR, "yes"),
+    ]));
+
+    assert!(errors.is_empty());
+    assert_eq!(Some("from.env.com".to_string()), config.registry_host);
+    // not overridden
+    assert_eq!(300, config.cache_max_age);
+    assert!(config.allowed_methods.allows(&Method::DELETE));
+    assert!(config.debug);
+}
+
+#[test]
+fn test_config_invalid_overrides() {
+    let mut config = Config::builder().cache_max_age(300).build();
+
+    let errors = config.apply_overrides(lookup([
+        (CACHE_MAX_AGE_ENV_VAR, "sixty"),
+        (ALLOWED_METHODS_ENV_VAR, "read-write"),
+    ]));
+
+    assert_eq!(1, errors.len());
+    assert!(matches!(&errors[0], ConfigError::Invalid { key, .. } if key == CACHE_MAX_AGE_ENV_VAR));
+    // invalid values are skipped, valid ones still apply
+    assert_eq!(300, config.cache_max_age);
+    assert_eq!(MethodPolicy::read_write(), config.allowed_methods);
+}
+
+#[test]
+fn test_config_validate() {
+    let config = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .build();
+
+    assert!(config.validate().is_empty());
+
+    assert_eq!(1, Config::default().validate().len());
+
+    for host in [
+        "https://123456789012.dkr.ecr.us-east-1.amazonaws.com",
+        "123456789012.dkr.ecr.us-east-1.amazonaws.com/",
+        "-bad.example.com",
+        "",
+        // not ECR
+        "docker.mycompany.com",
+        "12345.dkr.ecr.us-east-1.amazonaws.com",
+        "123456789012.dkr.ecr.useast1.amazonaws.com",
+    ] {
+        let config = Config::builder().registry_host(host).build();
+
+        assert_eq!(1, config.validate().len(), "{host:?} should be invalid");
+    }
+
+    let config = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .cache_max_age(CACHE_MAX_AGE_MAX + 1)
+        .build();
+
+    assert!(matches!(
+        &config.validate()[..],
+        [ConfigError::Invalid { key, .. }] if key == CACHE_MAX_AGE_ENV_VAR
+    ));
+
+    let config = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .cache(
+            CachePolicy::builder()
+                .digest(CacheDirectives::max_age(CACHE_MAX_AGE_MAX))
+                .tag(
+                    CacheDirectives::builder()
+                        .s_maxage(CACHE_MAX_AGE_MAX + 1)
+                        .build(),
+                )
+                .build(),
+        )
+        .build();
+
+    assert!(matches!(
+        &config.validate()[..],
+        [ConfigError::Invalid { key, .. }] if key == "cache.tag.s_maxage"
+    ));
+}
+
+#[test]
+fn test_config_load_strict() {
+    // lenient mode tolerates problems
+    let config = Config::load(lookup([(CACHE_MAX_AGE_ENV_VAR, "sixty")])).unwrap();
+    assert_eq!(CACHE_MAX_AGE_DEFAULT, config.cache_max_age);
+
+    // strict mode reports every problem
+    match Config::load(lookup([
+        (STRICT_CONFIG_ENV_VAR, "true"),
+        (CACHE_MAX_AGE_ENV_VAR, "sixty"),
+    ])) {
+        Err(ConfigError::Multiple(errors)) => assert_eq!(2, errors.len()),
+        other => panic!("expected multiple errors, got {other:?}"),
+    }
+
+    let config = Config::load(lookup([
+        (STRICT_CONFIG_ENV_VAR, "y"),
+        (
+            ECR_REGISTRY_ENV_VAR,
+            "123456789012.dkr.ecr.us-east-1.amazonaws.com",
+        ),
+    ]))
+    .unwrap();
+
+    assert!(config.strict);
+
+    // a reload TTL without a file to reload is a mistake
+    assert!(Config::load(lookup([(CONFIG_RELOAD_TTL_ENV_VAR, "60")])).is_ok());
+
+    match Config::load(lookup([
+        (STRICT_CONFIG_ENV_VAR, "true"),
+        (
+            ECR_REGISTRY_ENV_VAR,
+            "123456789012.dkr.ecr.us-east-1.amazonaws.com",
+        ),
+        (CONFIG_RELOAD_TTL_ENV_VAR, "60"),
+    ])) {
+        Err(ConfigError::Multiple(errors)) => assert!(matches!(
+            &errors[..],
+            [ConfigError::Invalid { key, .. }] if key == CONFIG_RELOAD_TTL_ENV_VAR
+        )),
+        other => panic!("expected an error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_config_for_request() {
+    let mut req = ApiGatewayRequestType::V1(Default::default());
+    req.stage_variables_mut().extend([
+        (
+            ECR_REGISTRY_ENV_VAR.to_string(),
+            "222222222222.dkr.ecr.us-west-2.amazonaws.com".to_string(),
+        ),
+        (
+            CACHE_MAX_AGE_ENV_VAR.to_string(),
+            "not a number".to_string(),
+        ),
+        ("unrelated".to_string(), "value".to_string()),
+    ]);
+
+    let config = Config::builder()
+        .registry_host("111111111111.dkr.ecr.us-east-1.amazonaws.com")
+        .cache_max_age(300)
+        .build();
+
+    // stage variables are ignored unless enabled
+    assert!(matches!(config.for_request(&req), (Cow::Borrowed(_), _)));
+
+    let config = Config {
+        stage_variables: true,
+        ..config
+    };
+
+    let (stage_config, errors) = config.for_request(&req);
+
+    assert_eq!(
+        Some("222222222222.dkr.ecr.us-west-2.amazonaws.com"),
+        stage_config.registry_host.as_deref()
+    );
+    // invalid values fall back to the environment configuration
+    assert_eq!(300, stage_config.cache_max_age);
+    assert!(matches!(
+        &errors[..],
+        [ConfigError::Invalid { key, .. }] if key == CACHE_MAX_AGE_ENV_VAR
+    ));
+
+    // a configuration which fails validation ignores the stage variables altogether
+    let mut rogue = req.clone();
+    rogue.stage_variables_mut().extend([
+        (
+            ECR_REGISTRY_ENV_VAR.to_string(),
+            "attacker.example.com".to_string(),
+        ),
+        (BASE_PATH_ENV_VAR.to_string(), "/registry".to_string()),
+    ]);
+
+    let (stage_config, errors) = config.for_request(&rogue);
+    assert_eq!(config, *stage_config);
+    assert!(
+        errors
+            .iter()
+            .any(|e| matches!(e, ConfigError::Invalid { key, .. } if key == ECR_REGISTRY_ENV_VAR))
+    );
+
+    // requests without stage variables use the environment configuration
+    let req = ApiGatewayRequestType::V2(Default::default());
+    assert_eq!(
+        Some("111111111111.dkr.ecr.us-east-1.amazonaws.com"),
+        config.for_request(&req).0.registry_host.as_deref()
+    );
+}
+
+#[test]
+fn test_config_for_request_routing_only() {
+    let mut req = ApiGatewayRequestType::V1(Default::default());
+    req.stage_variables_mut().extend(
+        [
+            (DEBUG_ENV_VAR, "true"),
+            (LOG_LEVEL_ENV_VAR, "trace"),
+            (STRICT_CONFIG_ENV_VAR, "true"),
+            (STAGE_VARIABLES_ENV_VAR, "false"),
+            (BASE_PATH_ENV_VAR, "/registry"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string())),
+    );
+
+    let config = Config::builder().stage_variables(true).build();
+    let (stage_config, errors) = config.for_request(&req);
+
+    // only settings deciding where requests are routed may be overridden
+    assert!(errors.is_empty());
+    assert_eq!(Some("/registry"), stage_config.base_path.as_deref());
+    assert_eq!(
+        Config {
+            base_path: Some("/registry".into()),
+            ..config.clone()
+        },
+        *stage_config
+    );
+}
+
+#[test]
+fn test_stage_configs() {
+    let mut req = ApiGatewayRequestType::V1(Default::default());
+    req.stage_variables_mut().insert(
+        ECR_REGISTRY_ENV_VAR.to_string(),
+        "222222222222.dkr.ecr.us-west-2.amazonaws.com".to_string(),
+    );
+    req.stage_variables_mut()
+        .insert(CACHE_MAX_AGE_ENV_VAR.to_string(), "sixty".to_string());
+
+    let config = Arc::new(
+        Config::builder()
+            .registry_host("111111111111.dkr.ecr.us-east-1.amazonaws.com")
+            .stage_variables(true)
+            .build(),
+    );
+    let stages = StageConfigs::default();
+
+    let (first, errors) = stages.get(&config, &req);
+    assert_eq!(
+        Some("222222222222.dkr.ecr.us-west-2.amazonaws.com"),
+        first.registry_host.as_deref()
+    );
+    assert_eq!(1, errors.len());
+
+    // the derived configuration is reused, and its errors are reported for every request
+    let (second, errors) = stages.get(&config, &req);
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(1, errors.len());
+
+    // a changed base configuration is derived afresh
+    let config = Arc::new(Config::clone(&config));
+    let (third, errors) = stages.get(&config, &req);
+    assert!(!Arc::ptr_eq(&first, &third));
+    assert_eq!(1, errors.len());
+
+    // requests without stage variables use the base configuration
+    let req = ApiGatewayRequestType::V2(Default::default());
+    assert!(Arc::ptr_eq(&config, &stages.get(&config, &req).0));
+}
+
+#[test]
+fn test_config_derived_registry_host() {
+    let mut config = Config::builder().account_id("123456789012").build();
+
+    // no region
+    assert!(config.resolved_registry_host().is_err());
+
+    let errors = config.apply_overrides(lookup([
+        (AWS_REGION_ENV_VAR, "us-west-2"),
+        (ECR_FIPS_ENV_VAR, "true"),
+    ]));
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        "123456789012.dkr.ecr-fips.us-west-2.amazonaws.com",
+        config.resolved_registry_host().unwrap()
+    );
+    assert!(config.validate().is_empty());
+
+    // the function's region does not override a configured one, but the ECR region does
+    let mut config = Config::builder()
+        .account_id("123456789012")
+        .region("cn-north-1")
+        .build();
+
+    config.apply_overrides(lookup([(AWS_REGION_ENV_VAR, "us-west-2")]));
+    assert_eq!(
+        "123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn",
+        config.resolved_registry_host().unwrap()
+    );
+
+    config.apply_overrides(lookup([
+        (ECR_REGION_ENV_VAR, "eu-west-1"),
+        (ECR_DUAL_STACK_ENV_VAR, "yes"),
+    ]));
+    assert_eq!(
+        "123456789012.dkr-ecr.eu-west-1.on.aws",
+        config.resolved_registry_host().unwrap()
+    );
+
+    // an explicit host takes precedence
+    config.apply_overrides(lookup([(
+        ECR_REGISTRY_ENV_VAR,
+        "210987654321.dkr.ecr.us-east-1.amazonaws.com",
+    )]));
+    assert_eq!(
+        "210987654321.dkr.ecr.us-east-1.amazonaws.com",
+        config.resolved_registry_host().unwrap()
+    );
+
+    // invalid derivations are reported by validation
+    let config = Config::builder()
+        .account_id("123456789012")
+        .region("cn-north-1")
+        .fips(true)
+        .build();
+
+    assert!(config.resolved_registry_host().is_err());
+    assert_eq!(1, config.validate().len());
+}
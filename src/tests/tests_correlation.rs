@@ -0,0 +1,53 @@
+use crate::correlation::{API_REQUEST_ID_HEADER, REQUEST_ID_HEADER, RequestIds, TRACE_ID_HEADER};
+use crate::requests::ApiGatewayRequestType;
+use aws_lambda_events::http::HeaderValue;
+use lambda_runtime::Context;
+
+#[test]
+fn test_request_ids() {
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+
+    if let ApiGatewayRequestType::V2(req) = &mut req {
+        req.request_context.request_id = Some("api-id".into());
+    }
+
+    req.headers_mut()
+        .insert(TRACE_ID_HEADER, HeaderValue::from_static("Root=1-header"));
+
+    let mut ctx = Context::default();
+    ctx.request_id = "lambda-id".into();
+
+    let ids = RequestIds::new(&ctx, Some(&req));
+
+    assert_eq!(Some("lambda-id"), ids.request_id.as_deref());
+    assert_eq!(Some("api-id"), ids.api_request_id.as_deref());
+    assert_eq!(Some("Root=1-header"), ids.trace_id.as_deref());
+    assert_eq!(
+        "request_id=lambda-id api_request_id=api-id trace_id=Root=1-header",
+        ids.to_string()
+    );
+    assert_eq!(
+        vec![
+            (REQUEST_ID_HEADER, "lambda-id"),
+            (API_REQUEST_ID_HEADER, "api-id"),
+            (TRACE_ID_HEADER, "Root=1-header"),
+        ],
+        ids.headers().collect::<Vec<_>>()
+    );
+
+    // the invocation's trace takes precedence over the header
+    ctx.xray_trace_id = Some("Root=1-lambda".into());
+    assert_eq!(
+        Some("Root=1-lambda".into()),
+        RequestIds::new(&ctx, Some(&req)).trace_id
+    );
+
+    // without a lambda request ID, the api gateway one identifies the request
+    let ids = RequestIds::from_request(&req);
+    assert_eq!(Some("api-id"), ids.primary());
+
+    let ids = RequestIds::new(&Context::default(), None);
+    assert!(ids.is_empty());
+    assert_eq!("", ids.to_string());
+    assert_eq!(0, ids.headers().count());
+}
@@ -0,0 +1,145 @@
+use crate::ecr::{
+    EcrHostError, Partition, is_account_id, is_ecr_registry_host, is_region, registry_host,
+};
+
+#[test]
+fn test_is_ecr_registry_host() {
+    for host in [
+        "123456789012.dkr.ecr.us-east-1.amazonaws.com",
+        "123456789012.dkr.ecr-fips.us-gov-west-1.amazonaws.com",
+        "123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn",
+        "123456789012.dkr-ecr.eu-west-2.on.aws",
+    ] {
+        assert!(is_ecr_registry_host(host), "{host:?} should be an ECR host");
+    }
+
+    for host in [
+        "docker.mycompany.com",
+        "public.ecr.aws",
+        "123456789012.dkr.ecr.us-east-1.amazonaws.com.evil.com",
+        "abcdefghijkl.dkr.ecr.us-east-1.amazonaws.com",
+        "123456789012.dkr.ecr.us-east.amazonaws.com",
+        "123456789012.dkr.ecr.us-iso-east-1.amazonaws.com",
+        "123456789012.dkr.ecr-fips.eu-west-1.amazonaws.com",
+    ] {
+        assert!(
+            !is_ecr_registry_host(host),
+            "{host:?} should not be an ECR host"
+        );
+    }
+}
+
+#[test]
+fn test_is_account_id() {
+    assert!(is_account_id("123456789012"));
+    assert!(!is_account_id("12345678901"));
+    assert!(!is_account_id("12345678901a"));
+}
+
+#[test]
+fn test_is_region() {
+    assert!(is_region("us-east-1"));
+    assert!(is_region("us-gov-west-1"));
+    assert!(is_region("ap-southeast-5"));
+    assert!(!is_region("us-east"));
+    assert!(!is_region("US-EAST-1"));
+    assert!(!is_region("us-east-0"));
+}
+
+#[test]
+fn test_partition_from_region() {
+    assert_eq!(Some(Partition::Aws), Partition::from_region("us-east-1"));
+    assert_eq!(Some(Partition::Aws), Partition::from_region("eu-central-2"));
+    assert_eq!(
+        Some(Partition::China),
+        Partition::from_region("cn-northwest-1")
+    );
+    assert_eq!(
+        Some(Partition::GovCloud),
+        Partition::from_region("us-gov-east-1")
+    );
+
+    // the isolated partitions are not supported
+    for region in ["us-iso-east-1", "us-isob-east-1", "eu-isoe-west-1"] {
+        assert_eq!(None, Partition::from_region(region), "{region:?}");
+    }
+}
+
+#[test]
+fn test_registry_host() {
+    let cases = [
+        (
+            "us-east-1",
+            false,
+            false,
+            "123456789012.dkr.ecr.us-east-1.amazonaws.com",
+        ),
+        (
+            "us-east-1",
+            true,
+            false,
+            "123456789012.dkr.ecr-fips.us-east-1.amazonaws.com",
+        ),
+        (
+            "us-east-1",
+            false,
+            true,
+            "123456789012.dkr-ecr.us-east-1.on.aws",
+        ),
+        (
+            "us-east-1",
+            true,
+            true,
+            "123456789012.dkr-ecr-fips.us-east-1.on.aws",
+        ),
+        (
+            "cn-north-1",
+            false,
+            false,
+            "123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn",
+        ),
+        (
+            "cn-north-1",
+            false,
+            true,
+            "123456789012.dkr-ecr.cn-north-1.on.amazonwebservices.com.cn",
+        ),
+        (
+            "us-gov-west-1",
+            true,
+            false,
+            "123456789012.dkr.ecr-fips.us-gov-west-1.amazonaws.com",
+        ),
+    ];
+
+    for (region, fips, dual_stack, expected) in cases {
+        let host = registry_host("123456789012", region, fips, dual_stack).unwrap();
+
+        assert_eq!(expected, host);
+        assert!(is_ecr_registry_host(&host));
+    }
+}
+
+#[test]
+fn test_registry_host_errors() {
+    assert_eq!(
+        Err(EcrHostError::InvalidAccountId("1234".into())),
+        registry_host("1234", "us-east-1", false, false)
+    );
+    assert_eq!(
+        Err(EcrHostError::InvalidRegion("useast1".into())),
+        registry_host("123456789012", "useast1", false, false)
+    );
+    assert_eq!(
+        Err(EcrHostError::UnsupportedPartition("us-iso-east-1".into())),
+        registry_host("123456789012", "us-iso-east-1", false, false)
+    );
+
+    // FIPS endpoints are only offered in the US commercial and GovCloud regions
+    for region in ["cn-north-1", "eu-west-1", "ap-southeast-2"] {
+        assert_eq!(
+            Err(EcrHostError::FipsUnsupported(region.into())),
+            registry_host("123456789012", region, true, false)
+        );
+    }
+}
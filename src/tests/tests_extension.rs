@@ -0,0 +1,106 @@
+use crate::extension::{EXTENSION_NAME, RUNTIME_API_ENV_VAR, register_shutdown_extension};
+use std::env;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+/// Utility: read a request to the fake runtime API, returning its head and body
+async fn read_request(stream: &mut BufReader<TcpStream>) -> (Vec<String>, String) {
+    let mut head = vec![];
+
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+
+        if line.trim().is_empty() {
+            break;
+        }
+
+        head.push(line.trim().to_string());
+    }
+
+    let length = header(&head, "content-length").map_or(0, |length| length.parse().unwrap());
+
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.unwrap();
+
+    (head, String::from_utf8(body).unwrap())
+}
+
+/// Utility: the value of a header in the head of a request
+fn header<'a>(head: &'a [String], name: &str) -> Option<&'a str> {
+    head.iter().find_map(|line| {
+        line.split_once(':')
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    })
+}
+
+/// Utility: serve the runtime API from a listener, which the extension finds in the environment
+async fn runtime_api() -> TcpListener {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    // SAFETY: no other test reads or writes the environment variable
+    unsafe {
+        env::set_var(
+            RUNTIME_API_ENV_VAR,
+            listener.local_addr().unwrap().to_string(),
+        )
+    };
+
+    listener
+}
+
+#[tokio::test]
+async fn test_register_shutdown_extension() {
+    // registration fails if the runtime API refuses it
+    let listener = runtime_api().await;
+
+    let server = tokio::spawn(async move {
+        let mut stream = BufReader::new(listener.accept().await.unwrap().0);
+        read_request(&mut stream).await;
+
+        stream
+            .write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+    });
+
+    assert!(register_shutdown_extension().await.is_err());
+    server.await.unwrap();
+
+    let listener = runtime_api().await;
+    let (polled, next) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let mut stream = BufReader::new(listener.accept().await.unwrap().0);
+        let (head, body) = read_request(&mut stream).await;
+
+        assert_eq!("POST /2020-01-01/extension/register HTTP/1.1", head[0]);
+        assert_eq!(Some(EXTENSION_NAME), header(&head, "Lambda-Extension-Name"));
+        assert_eq!(r#"{"events":[]}"#, body);
+
+        let body = r#"{"functionName":"rewrite","functionVersion":"1","handler":"bootstrap"}"#;
+
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nLambda-Extension-Identifier: ext-id\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        // the extension then waits for events, which never arrive
+        let mut stream = BufReader::new(listener.accept().await.unwrap().0);
+        polled.send(read_request(&mut stream).await.0).unwrap();
+    });
+
+    register_shutdown_extension().await.unwrap();
+
+    let head = next.await.unwrap();
+    assert_eq!("GET /2020-01-01/extension/event/next HTTP/1.1", head[0]);
+    assert_eq!(Some("ext-id"), header(&head, "Lambda-Extension-Identifier"));
+}
@@ -0,0 +1,311 @@
+use crate::config::{
+    Config, ConfigError, ConfigFormat, DEBUG_ENV_VAR, DEBUG_SAMPLE_RATE_ENV_VAR,
+    DEBUG_SECRET_ENV_VAR, LOG_LEVEL_ENV_VAR, LOG_RATE_LIMIT_ENV_VAR,
+};
+use crate::correlation::RequestIds;
+use crate::logging::{
+    DEBUG_HEADER, LOG_LIMITER_MAX_KEYS, Level, LogEntry, LogLimit, LogLimiter, LogStatus, Logger,
+    SampleRate, constant_time_eq,
+};
+use crate::redaction::{REDACTED, Redaction};
+use crate::requests::ApiGatewayRequestType;
+use aws_lambda_events::http::HeaderValue;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_level_parse() {
+    assert_eq!(Ok(Level::Error), "error".parse());
+    assert_eq!(Ok(Level::Warn), "WARN".parse());
+    assert_eq!(Ok(Level::Warn), "warning".parse());
+    assert_eq!(Ok(Level::Trace), " trace ".parse());
+    assert!("verbose".parse::<Level>().is_err());
+
+    assert_eq!(Level::Info, Level::default());
+    assert!(Level::Error < Level::Warn && Level::Debug < Level::Trace);
+}
+
+#[test]
+fn test_logger_enabled() {
+    let logger = Logger::new(Level::Warn);
+
+    assert!(logger.enabled(Level::Error));
+    assert!(logger.enabled(Level::Warn));
+    assert!(!logger.enabled(Level::Info));
+
+    // entries at disabled levels are never built
+    logger.log(Level::Debug, || -> LogEntry { unreachable!() });
+
+    assert!(Logger::new(Level::Trace).enabled(Level::Trace));
+}
+
+#[test]
+fn test_log_entry_line() {
+    let entry = LogEntry::builder()
+        .message("Handled request")
+        .ids(RequestIds {
+            request_id: Some("lambda-id".into()),
+            api_request_id: None,
+            trace_id: Some("Root=1-abc".into()),
+        })
+        .host("docker.example.com")
+        .path("/v2/library/ubuntu/manifests/latest")
+        .decision("redirect")
+        .status(307)
+        .latency_ms(Duration::from_micros(1500))
+        .build();
+
+    let line = entry.to_line(Level::Info);
+
+    assert!(!line.contains('\n'));
+    assert_eq!(
+        json!({
+            "level": "info",
+            "message": "Handled request",
+            "request_id": "lambda-id",
+            "trace_id": "Root=1-abc",
+            "host": "docker.example.com",
+            "path": "/v2/library/ubuntu/manifests/latest",
+            "decision": "redirect",
+            "status": 307,
+            "latency_ms": 1.5,
+        }),
+        serde_json::from_str::<Value>(&line).unwrap()
+    );
+
+    // unset fields are omitted
+    assert_eq!(
+        json!({"level": "warn", "message": "Something happened"}),
+        serde_json::from_str::<Value>(&LogEntry::from("Something happened").to_line(Level::Warn))
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_config_logger() {
+    assert_eq!(Level::Info, Config::default().logger().level());
+
+    let mut config = Config::default();
+    let errors = config.apply_overrides(|name| (name == LOG_LEVEL_ENV_VAR).then(|| "warn".into()));
+
+    assert!(errors.is_empty());
+    assert_eq!(Level::Warn, config.log_level);
+    assert_eq!(Level::Warn, config.logger().level());
+
+    // debug raises the level to at least debug, but does not lower it
+    config.apply_overrides(|name| (name == DEBUG_ENV_VAR).then(|| "yes".into()));
+    assert_eq!(Level::Debug, config.logger().level());
+
+    config.log_level = Level::Trace;
+    assert_eq!(Level::Trace, config.logger().level());
+
+    let errors = config.apply_overrides(|name| (name == LOG_LEVEL_ENV_VAR).then(|| "loud".into()));
+    assert_eq!(1, errors.len());
+    assert_eq!(Level::Trace, config.log_level);
+
+    assert_eq!(
+        Level::Debug,
+        Config::parse(r#"log_level = "debug""#, ConfigFormat::Toml)
+            .unwrap()
+            .log_level
+    );
+}
+
+#[test]
+fn test_log_limiter() {
+    let limiter = LogLimiter::new();
+    let limit = LogLimit::builder().burst(2).interval(60).build();
+    let start = Instant::now();
+
+    let emitted = |suppressed| LogStatus::Emitted { suppressed };
+
+    assert_eq!(emitted(0), limiter.check_at("a", &limit, start));
+    assert_eq!(emitted(0), limiter.check_at("a", &limit, start));
+    assert_eq!(LogStatus::Ignored, limiter.check_at("a", &limit, start));
+    assert_eq!(LogStatus::Ignored, limiter.check_at("a", &limit, start));
+
+    // other keys have their own budget
+    assert_eq!(emitted(0), limiter.check_at("b", &limit, start));
+
+    // one line is refilled every 30 seconds, reporting what was suppressed meanwhile
+    let later = start + Duration::from_secs(29);
+    assert_eq!(LogStatus::Ignored, limiter.check_at("a", &limit, later));
+
+    let later = start + Duration::from_secs(30);
+    assert_eq!(emitted(3), limiter.check_at("a", &limit, later));
+    assert_eq!(LogStatus::Ignored, limiter.check_at("a", &limit, later));
+
+    // the budget refills no further than the burst
+    let later = start + Duration::from_secs(3600);
+    assert_eq!(emitted(1), limiter.check_at("a", &limit, later));
+    assert_eq!(emitted(0), limiter.check_at("a", &limit, later));
+    assert_eq!(LogStatus::Ignored, limiter.check_at("a", &limit, later));
+}
+
+#[test]
+fn test_log_limiter_max_keys() {
+    let limiter = LogLimiter::new();
+    let limit = LogLimit::builder().burst(1).interval(60).build();
+    let start = Instant::now();
+
+    for i in 0..LOG_LIMITER_MAX_KEYS {
+        limiter.check_at(&format!("client-{i}"), &limit, start);
+    }
+
+    // every budget is in use, so new keys are ignored rather than growing without bound
+    assert_eq!(LogStatus::Ignored, limiter.check_at("new", &limit, start));
+
+    // once budgets have refilled, they are forgotten to make room
+    let later = start + Duration::from_secs(60);
+    assert_eq!(
+        LogStatus::Emitted { suppressed: 0 },
+        limiter.check_at("new", &limit, later)
+    );
+}
+
+#[test]
+fn test_log_limit_config() {
+    assert_eq!(
+        Ok(LogLimit::builder().burst(10).interval(300).build()),
+        " 10 / 300 ".parse()
+    );
+    assert!("10".parse::<LogLimit>().is_err());
+    assert!("ten/60".parse::<LogLimit>().is_err());
+
+    let mut config = Config::default();
+    let errors =
+        config.apply_overrides(|name| (name == LOG_RATE_LIMIT_ENV_VAR).then(|| "1/10".into()));
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        LogLimit::builder().burst(1).interval(10).build(),
+        config.log_limit
+    );
+
+    config.log_limit.interval = 0;
+    assert!(
+        config.validate().iter().any(
+            |e| matches!(e, ConfigError::Invalid { key, .. } if key == LOG_RATE_LIMIT_ENV_VAR)
+        )
+    );
+}
+
+#[test]
+fn test_log_limited() {
+    let limiter = LogLimiter::new();
+    let limit = LogLimit::builder().burst(1).interval(60).build();
+
+    // lines at disabled levels do not use up the budget
+    let status =
+        Logger::new(Level::Error).log_limited(&limiter, "key", &limit, Level::Warn, || {
+            LogEntry::from("ignored")
+        });
+    assert_eq!(LogStatus::Ignored, status);
+
+    let logger = Logger::new(Level::Warn);
+    let log = || logger.log_limited(&limiter, "key", &limit, Level::Warn, || "limited");
+
+    assert_eq!(LogStatus::Emitted { suppressed: 0 }, log());
+    assert_eq!(LogStatus::Ignored, log());
+}
+
+#[test]
+fn test_sample_rate() {
+    assert_eq!(
+        Ok(0.25),
+        "0.25".parse::<SampleRate>().map(|rate| rate.get())
+    );
+    assert!("1.5".parse::<SampleRate>().is_err());
+    assert!("-0.1".parse::<SampleRate>().is_err());
+    assert!("NaN".parse::<SampleRate>().is_err());
+    assert!(serde_json::from_str::<SampleRate>("2.0").is_err());
+
+    assert!(!(0..100).any(|_| SampleRate::NEVER.sample()));
+    assert!((0..100).all(|_| SampleRate::ALWAYS.sample()));
+
+    let half = SampleRate::new(0.5).unwrap();
+    let sampled = (0..10_000).filter(|_| half.sample()).count();
+    assert!(
+        (4_000..6_000).contains(&sampled),
+        "sampled {sampled} of 10000"
+    );
+}
+
+#[test]
+fn test_request_logger() {
+    let secret = "0123456789abcdef";
+    let mut config = Config::default();
+
+    let errors = config.apply_overrides(|name| match name {
+        DEBUG_SECRET_ENV_VAR => Some(secret.into()),
+        DEBUG_SAMPLE_RATE_ENV_VAR => Some("0".into()),
+        _ => None,
+    });
+
+    assert!(errors.is_empty());
+    assert!(
+        config
+            .validate()
+            .iter()
+            .all(|e| !matches!(e, ConfigError::Invalid { key, .. } if key == DEBUG_SECRET_ENV_VAR))
+    );
+
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    assert_eq!(Level::Info, config.request_logger(&req).level());
+
+    // the wrong secret changes nothing
+    req.headers_mut()
+        .insert(DEBUG_HEADER, HeaderValue::from_static("0123456789abcdeF"));
+    assert_eq!(Level::Info, config.request_logger(&req).level());
+
+    req.headers_mut()
+        .insert(DEBUG_HEADER, HeaderValue::from_static(secret));
+    assert_eq!(Level::Trace, config.request_logger(&req).level());
+
+    // without a secret configured, the header is ignored
+    config.debug_secret = None;
+    assert_eq!(Level::Info, config.request_logger(&req).level());
+
+    config.debug_sample_rate = SampleRate::ALWAYS;
+    assert_eq!(Level::Debug, config.request_logger(&req).level());
+
+    // sampling does not lower the configured level
+    config.log_level = Level::Trace;
+    assert_eq!(Level::Trace, config.request_logger(&req).level());
+
+    // the header is never logged
+    let redacted = Redaction::default().redacted(&serde_json::to_value(&req).unwrap());
+    assert_eq!(
+        REDACTED,
+        redacted["headers"][DEBUG_HEADER.to_ascii_lowercase()]
+    );
+}
+
+#[test]
+fn test_debug_config() {
+    let mut config = Config::default();
+
+    let errors = config.apply_overrides(|name| match name {
+        DEBUG_SECRET_ENV_VAR => Some("short".into()),
+        DEBUG_SAMPLE_RATE_ENV_VAR => Some("10%".into()),
+        _ => None,
+    });
+
+    assert_eq!(1, errors.len());
+    assert!(
+        matches!(&errors[0], ConfigError::Invalid { key, .. } if key == DEBUG_SAMPLE_RATE_ENV_VAR)
+    );
+    assert!(
+        config
+            .validate()
+            .iter()
+            .any(|e| matches!(e, ConfigError::Invalid { key, .. } if key == DEBUG_SECRET_ENV_VAR))
+    );
+
+    // the secret is never written out with the configuration
+    assert!(!serde_json::to_string(&config).unwrap().contains("short"));
+
+    assert!(constant_time_eq(b"secret", b"secret"));
+    assert!(!constant_time_eq(b"secret", b"secreT"));
+    assert!(!constant_time_eq(b"secret", b"secrets"));
+}
@@ -0,0 +1,73 @@
+use crate::create_method_not_allowed_response;
+use crate::methods::MethodPolicy;
+use crate::oci::{ErrorCode, ErrorResponse};
+use crate::requests::ApiGatewayRequestType;
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::Method;
+
+#[test]
+fn test_method_policy_presets() {
+    let read_only = MethodPolicy::read_only();
+
+    assert!(read_only.allows(&Method::GET));
+    assert!(read_only.allows(&Method::HEAD));
+    assert!(!read_only.allows(&Method::PUT));
+    assert!(!read_only.allows(&Method::DELETE));
+
+    let read_write = MethodPolicy::read_write();
+
+    assert!(read_write.allows(&Method::PUT));
+    assert!(read_write.allows(&Method::PATCH));
+    assert!(!read_write.allows(&Method::DELETE));
+
+    assert_eq!(MethodPolicy::read_only(), MethodPolicy::default());
+}
+
+#[test]
+fn test_method_policy_parse() {
+    assert_eq!(
+        Ok(MethodPolicy::read_only()),
+        "read-only".parse::<MethodPolicy>()
+    );
+    assert_eq!(
+        Ok(MethodPolicy::read_write()),
+        " Read-Write ".parse::<MethodPolicy>()
+    );
+    assert_eq!(
+        Ok(MethodPolicy::new([
+            Method::GET,
+            Method::HEAD,
+            Method::DELETE
+        ])),
+        "get, HEAD,delete,get".parse::<MethodPolicy>()
+    );
+    assert!("".parse::<MethodPolicy>().is_err());
+    assert!("GET,(".parse::<MethodPolicy>().is_err());
+}
+
+#[test]
+fn test_create_method_not_allowed_response() {
+    for mut req in [
+        ApiGatewayRequestType::V1(Default::default()),
+        ApiGatewayRequestType::V2(Default::default()),
+    ] {
+        req.set_method(Method::DELETE);
+
+        let resp = create_method_not_allowed_response(
+            &req,
+            &MethodPolicy::read_only(),
+            &Default::default(),
+        );
+
+        assert_eq!(405, resp.status_code());
+        assert_eq!("GET, HEAD", resp.headers().get("Allow").unwrap());
+
+        match resp.body() {
+            Some(Body::Text(body)) => {
+                let body: ErrorResponse = serde_json::from_str(body).unwrap();
+                assert_eq!(ErrorCode::Unsupported, body.errors[0].code);
+            }
+            _ => panic!("returned non-text body"),
+        }
+    }
+}
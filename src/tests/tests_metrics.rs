@@ -0,0 +1,148 @@
+use crate::config::{Config, ConfigError, METRICS_ENV_VAR, METRICS_NAMESPACE_ENV_VAR};
+use crate::metrics::{DEFAULT_METRICS_NAMESPACE, RequestMetrics, UNKNOWN_EVENT_SOURCE};
+use serde_json::json;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn test_request_metrics_emf() {
+    let metrics = RequestMetrics::builder()
+        .event_source("apigateway-v2")
+        .host("docker.example.com")
+        .repository("library/ubuntu")
+        .endpoint("manifest")
+        .status(307)
+        .redirected(true)
+        .allow_listed(true)
+        .latency(Duration::from_micros(2500))
+        .build();
+
+    assert_eq!(
+        json!({
+            "_aws": {
+                "Timestamp": 1_700_000_000_000u64,
+                "CloudWatchMetrics": [{
+                    "Namespace": "Registry",
+                    "Dimensions": [
+                        ["EventSource"],
+                        ["StatusCode"],
+                        ["Endpoint"],
+                        ["Host"],
+                        ["Host", "Repository"],
+                    ],
+                    "Metrics": [
+                        {"Name": "Requests", "Unit": "Count"},
+                        {"Name": "Redirects", "Unit": "Count"},
+                        {"Name": "Errors", "Unit": "Count"},
+                        {"Name": "ClientErrors", "Unit": "Count"},
+                        {"Name": "ServerErrors", "Unit": "Count"},
+                        {"Name": "ConfigErrors", "Unit": "Count"},
+                        {"Name": "Latency", "Unit": "Milliseconds"},
+                    ],
+                }],
+            },
+            "EventSource": "apigateway-v2",
+            "StatusCode": "307",
+            "Endpoint": "manifest",
+            "Host": "docker.example.com",
+            "Repository": "library/ubuntu",
+            "Requests": 1,
+            "Redirects": 1,
+            "Errors": 0,
+            "ClientErrors": 0,
+            "ServerErrors": 0,
+            "ConfigErrors": 0,
+            "Latency": 2.5,
+        }),
+        metrics.to_emf("Registry", UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+    );
+}
+
+#[test]
+fn test_request_metrics_dimensions() {
+    // dimension sets are only published when all of their dimensions are known
+    let emf = RequestMetrics::builder()
+        .event_source(UNKNOWN_EVENT_SOURCE)
+        .status(500)
+        .config_error(true)
+        .latency(Duration::ZERO)
+        .build()
+        .to_emf(DEFAULT_METRICS_NAMESPACE, UNIX_EPOCH);
+
+    assert_eq!(
+        json!([["EventSource"], ["StatusCode"]]),
+        emf["_aws"]["CloudWatchMetrics"][0]["Dimensions"]
+    );
+    assert!(emf.get("Host").is_none());
+    assert_eq!(json!(1), emf["Errors"]);
+    assert_eq!(json!(1), emf["ServerErrors"]);
+    assert_eq!(json!(1), emf["ConfigErrors"]);
+
+    let emf = RequestMetrics::builder()
+        .event_source("apigateway-v1")
+        .host("docker.example.com")
+        .status(405)
+        .latency(Duration::ZERO)
+        .build()
+        .to_emf(DEFAULT_METRICS_NAMESPACE, UNIX_EPOCH);
+
+    assert_eq!(
+        json!([["EventSource"], ["StatusCode"]]),
+        emf["_aws"]["CloudWatchMetrics"][0]["Dimensions"]
+    );
+    assert_eq!(json!(1), emf["ClientErrors"]);
+
+    // the host and repository of rejected requests are recorded, but not as dimensions
+    let emf = RequestMetrics::builder()
+        .event_source("apigateway-v2")
+        .host("docker.example.com")
+        .repository("internal/attacker-chosen-name")
+        .endpoint("manifest")
+        .status(403)
+        .latency(Duration::ZERO)
+        .build()
+        .to_emf(DEFAULT_METRICS_NAMESPACE, UNIX_EPOCH);
+
+    assert_eq!(
+        json!([["EventSource"], ["StatusCode"], ["Endpoint"]]),
+        emf["_aws"]["CloudWatchMetrics"][0]["Dimensions"]
+    );
+    assert_eq!(json!("docker.example.com"), emf["Host"]);
+    assert_eq!(json!("internal/attacker-chosen-name"), emf["Repository"]);
+
+    // without an allow list, any repository is redirected, so it is not a dimension either
+    let emf = RequestMetrics::builder()
+        .event_source("apigateway-v2")
+        .host("docker.example.com")
+        .repository("random-8f3a2c")
+        .endpoint("manifest")
+        .status(307)
+        .redirected(true)
+        .latency(Duration::ZERO)
+        .build()
+        .to_emf(DEFAULT_METRICS_NAMESPACE, UNIX_EPOCH);
+
+    assert_eq!(
+        json!([["EventSource"], ["StatusCode"], ["Endpoint"], ["Host"]]),
+        emf["_aws"]["CloudWatchMetrics"][0]["Dimensions"]
+    );
+    assert_eq!(json!("random-8f3a2c"), emf["Repository"]);
+}
+
+#[test]
+fn test_metrics_config() {
+    let mut config = Config::default();
+    assert!(!config.metrics.enabled);
+    assert_eq!(DEFAULT_METRICS_NAMESPACE, config.metrics.namespace);
+
+    let errors = config.apply_overrides(|name| match name {
+        METRICS_ENV_VAR => Some("true".into()),
+        METRICS_NAMESPACE_ENV_VAR => Some(" ".into()),
+        _ => None,
+    });
+
+    assert!(errors.is_empty());
+    assert!(config.metrics.enabled);
+    assert!(config.validate().iter().any(
+        |e| matches!(e, ConfigError::Invalid { key, .. } if key == METRICS_NAMESPACE_ENV_VAR)
+    ));
+}
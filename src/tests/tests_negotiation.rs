@@ -0,0 +1,123 @@
+use crate::negotiation::{MediaRange, MediaType, negotiate, parse_accept};
+
+const ALL: [MediaType; 4] = [
+    MediaType::Html,
+    MediaType::Json,
+    MediaType::ProblemJson,
+    MediaType::Text,
+];
+
+#[test]
+fn test_media_range_parse() {
+    assert_eq!(
+        Some(MediaRange {
+            type_: "text".into(),
+            subtype: "html".into(),
+            quality: 1.0,
+        }),
+        MediaRange::parse(" Text/HTML ")
+    );
+    assert_eq!(
+        Some(0.25),
+        MediaRange::parse("application/json; charset=utf-8; q=0.25").map(|r| r.quality)
+    );
+    assert_eq!(
+        Some(1.0),
+        MediaRange::parse("*/*;q=1.000").map(|r| r.quality)
+    );
+
+    for invalid in [
+        "",
+        "text",
+        "*/html",
+        "text/",
+        "text/html;q=2",
+        "text/html;q=0.1234",
+        "text/html;q=-1",
+        "text/html;q=1.5",
+        "text/html;q=high",
+    ] {
+        assert_eq!(
+            None,
+            MediaRange::parse(invalid),
+            "{invalid:?} should be invalid"
+        );
+    }
+
+    // malformed ranges are skipped
+    assert_eq!(2, parse_accept("text/html, nonsense, */*;q=0.1").len());
+}
+
+#[test]
+fn test_negotiate() {
+    assert_eq!(Some(MediaType::Html), negotiate(None, &ALL));
+    assert_eq!(Some(MediaType::Json), negotiate(None, &ALL[1..]));
+    assert_eq!(None, negotiate(None, &[]));
+
+    // wildcards leave the choice to the server's order
+    assert_eq!(Some(MediaType::Html), negotiate(Some("*/*"), &ALL));
+    assert_eq!(
+        Some(MediaType::Json),
+        negotiate(Some("application/*"), &ALL)
+    );
+
+    // exact matches
+    assert_eq!(
+        Some(MediaType::ProblemJson),
+        negotiate(Some("application/problem+json"), &ALL)
+    );
+    assert_eq!(Some(MediaType::Text), negotiate(Some("text/plain"), &ALL));
+
+    // the highest quality wins
+    assert_eq!(
+        Some(MediaType::Text),
+        negotiate(Some("text/html;q=0.2, text/plain;q=0.8, */*;q=0.1"), &ALL)
+    );
+
+    // more specific ranges take precedence over less specific ones, even with lower quality
+    assert_eq!(
+        Some(MediaType::Html),
+        negotiate(Some("text/*;q=0.9, text/plain;q=0.1"), &ALL)
+    );
+
+    // q=0 excludes a type
+    assert_eq!(
+        Some(MediaType::Json),
+        negotiate(Some("text/html;q=0, */*"), &ALL)
+    );
+
+    // other JSON types are understood as JSON
+    assert_eq!(
+        Some(MediaType::Json),
+        negotiate(
+            Some(
+                "application/vnd.oci.image.index.v1+json, application/vnd.docker.distribution.manifest.v2+json"
+            ),
+            &ALL
+        )
+    );
+    assert_eq!(
+        Some(MediaType::Html),
+        negotiate(Some("text/html, application/vnd.api+json;q=0.5"), &ALL)
+    );
+    // which does not shadow an exact match for problem details
+    assert_eq!(
+        Some(MediaType::ProblemJson),
+        negotiate(Some("application/problem+json"), &ALL)
+    );
+    assert_eq!(None, negotiate(Some("application/vnd.api+xml"), &ALL));
+
+    // nothing acceptable
+    assert_eq!(None, negotiate(Some("image/png"), &ALL));
+    assert_eq!(None, negotiate(Some("*/*;q=0"), &ALL));
+    assert_eq!(None, negotiate(Some(""), &ALL));
+}
+
+#[test]
+fn test_media_type() {
+    assert_eq!(
+        "application/problem+json",
+        MediaType::ProblemJson.to_string()
+    );
+    assert_eq!("text/plain; charset=utf-8", MediaType::Text.content_type());
+}
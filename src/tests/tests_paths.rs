@@ -0,0 +1,98 @@
+use crate::create_rewrite_response;
+use crate::oci::{ErrorCode, ErrorResponse};
+use crate::paths::{PathError, normalize_path};
+use crate::requests::ApiGatewayRequestType;
+use aws_lambda_events::encodings::Body;
+
+#[test]
+fn test_normalize_path_passthrough() {
+    assert_eq!(
+        Ok("/v2/library/ubuntu/manifests/sha256:abc123".to_string()),
+        normalize_path("/v2/library/ubuntu/manifests/sha256:abc123")
+    );
+    assert_eq!(Ok("/".to_string()), normalize_path(""));
+    assert_eq!(Ok("/".to_string()), normalize_path("/"));
+    assert_eq!(Ok("/a/b/c".to_string()), normalize_path("a/b/c"));
+}
+
+#[test]
+fn test_normalize_path_trailing_slash() {
+    assert_eq!(Ok("/v2/".to_string()), normalize_path("/v2/"));
+    assert_eq!(Ok("/v2".to_string()), normalize_path("/v2"));
+}
+
+#[test]
+fn test_normalize_path_collapses_slashes() {
+    assert_eq!(
+        Ok("/v2/library/ubuntu/tags/list".to_string()),
+        normalize_path("/v2//library///ubuntu/tags/list")
+    );
+}
+
+#[test]
+fn test_normalize_path_encoding() {
+    assert_eq!(
+        Ok("/v2/my%20repo/caf%C3%A9".to_string()),
+        normalize_path("/v2/my repo/café")
+    );
+    // already-encoded input is not double-encoded
+    assert_eq!(
+        Ok("/v2/my%20repo/caf%C3%A9".to_string()),
+        normalize_path("/v2/my%20repo/caf%c3%a9")
+    );
+    // an encoded slash stays within its segment
+    assert_eq!(Ok("/v2/a%2Fb".to_string()), normalize_path("/v2/a%2fb"));
+}
+
+#[test]
+fn test_normalize_path_dot_segments() {
+    assert_eq!(Err(PathError::DotSegment), normalize_path("/v2/../admin"));
+    assert_eq!(Err(PathError::DotSegment), normalize_path("/v2/./x"));
+    assert_eq!(Err(PathError::DotSegment), normalize_path("/v2/%2e%2E/x"));
+    assert_eq!(Ok("/v2/.../x".to_string()), normalize_path("/v2/.../x"));
+}
+
+#[test]
+fn test_normalize_path_protocol_relative() {
+    assert_eq!(
+        Err(PathError::ProtocolRelative),
+        normalize_path("//evil.com/v2/")
+    );
+    assert_eq!(
+        Err(PathError::ProtocolRelative),
+        normalize_path("/\\evil.com/v2/")
+    );
+}
+
+#[test]
+fn test_create_rewrite_response_invalid_path() {
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("//evil.com/v2/");
+
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
+
+    assert_eq!(400, resp.status_code());
+    assert!(!resp.headers().contains_key("Location"));
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(ErrorCode::NameInvalid, body.errors[0].code);
+        }
+        _ => panic!("returned non-text body"),
+    }
+}
+
+#[test]
+fn test_create_rewrite_response_encoded_path() {
+    let mut req = ApiGatewayRequestType::V1(Default::default());
+    req.set_path("/v2/some repo/tags/list");
+
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
+
+    assert_eq!(307, resp.status_code());
+    assert_eq!(
+        "https://ecr.myhost.com/v2/some%20repo/tags/list",
+        resp.headers().get("Location").unwrap()
+    );
+}
@@ -0,0 +1,146 @@
+use super::fixtures::{APIGW_REQ_V1, APIGW_REQ_V2};
+use crate::config::{Config, ConfigError, REDACT_HEADERS_ENV_VAR, REDACT_PATHS_ENV_VAR};
+use crate::redaction::{REDACTED, Redaction};
+use crate::requests::ApiGatewayRequestType;
+use crate::rewriter::Rewriter;
+use serde_json::{Value, json};
+
+#[test]
+fn test_redact_defaults() {
+    let mut event: Value = serde_json::from_str(APIGW_REQ_V1).unwrap();
+
+    event["headers"]["authorization"] = "Basic c2VjcmV0".into();
+    event["multiValueHeaders"]["Authorization"] = json!(["Basic c2VjcmV0", "Bearer abc"]);
+    event["requestContext"]["identity"]["user"] = Value::Null;
+    event["queryStringParameters"] = json!({"token": "secret"});
+    event["multiValueQueryStringParameters"] = json!({"token": ["secret"]});
+
+    Redaction::default().redact(&mut event);
+
+    // headers are matched regardless of case, and arrays keep their shape
+    assert_eq!(REDACTED, event["headers"]["authorization"]);
+    assert_eq!(
+        json!([REDACTED, REDACTED]),
+        event["multiValueHeaders"]["Authorization"]
+    );
+
+    let identity = &event["requestContext"]["identity"];
+    assert_eq!(REDACTED, identity["sourceIp"]);
+    assert_eq!(REDACTED, identity["cognitoIdentityId"]);
+    assert_eq!(REDACTED, identity["cognitoAuthenticationProvider"]);
+    assert_eq!(REDACTED, identity["userArn"]);
+    assert_eq!(REDACTED, identity["apiKey"]);
+    // objects are masked as a whole
+    assert_eq!(REDACTED, event["requestContext"]["authorizer"]);
+    assert_eq!(REDACTED, event["queryStringParameters"]);
+    assert_eq!(REDACTED, event["multiValueQueryStringParameters"]);
+
+    // unset values are left unset
+    assert_eq!(Value::Null, identity["user"]);
+
+    // everything else is left as is
+    assert_eq!(
+        "gy415nuibc.execute-api.us-east-1.amazonaws.com",
+        event["headers"]["Host"]
+    );
+    assert_eq!("/hello/world", event["path"]);
+
+    let mut event: Value = serde_json::from_str(APIGW_REQ_V2).unwrap();
+    event["rawQueryString"] = "token=secret".into();
+
+    let event = Redaction::default().redacted(&event);
+    assert_eq!(REDACTED, event["requestContext"]["http"]["sourceIp"]);
+    assert_eq!(REDACTED, event["rawQueryString"]);
+}
+
+#[test]
+fn test_redact_configured() {
+    let redaction = Redaction::builder()
+        .headers(vec!["Host".into()])
+        .paths(vec!["stageVariables.*".into(), "items.*.secret".into()])
+        .build();
+
+    let mut payload = json!({
+        "headers": {"host": "example.com", "Authorization": "Basic c2VjcmV0"},
+        "stageVariables": {"a": "1", "b": "2"},
+        "items": [{"secret": "x", "public": "y"}, {"public": "z"}],
+    });
+
+    redaction.redact(&mut payload);
+
+    assert_eq!(
+        json!({
+            "headers": {"host": REDACTED, "Authorization": "Basic c2VjcmV0"},
+            "stageVariables": {"a": REDACTED, "b": REDACTED},
+            "items": [{"secret": REDACTED, "public": "y"}, {"public": "z"}],
+        }),
+        payload
+    );
+}
+
+#[test]
+fn test_redaction_config() {
+    let mut config = Config::default();
+
+    let errors = config.apply_overrides(|name| match name {
+        REDACT_HEADERS_ENV_VAR => Some("Authorization, X-Custom-Token,".into()),
+        REDACT_PATHS_ENV_VAR => Some("requestContext..sourceIp".into()),
+        _ => None,
+    });
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        vec!["Authorization".to_string(), "X-Custom-Token".to_string()],
+        config.redaction.headers
+    );
+    assert!(
+        config
+            .validate()
+            .iter()
+            .any(|e| matches!(e, ConfigError::Invalid { key, .. } if key == REDACT_PATHS_ENV_VAR))
+    );
+}
+
+#[test]
+fn test_redact_redirect_location() {
+    let config = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .build();
+
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/v2/library/ubuntu/tags/list");
+    req.set_raw_query("n=10&token=SECRET");
+
+    let resp = Rewriter::new(config.clone()).respond(&req);
+    assert_eq!(307, resp.status_code());
+
+    // the query of the request is copied into the location, so it is masked there too
+    let resp = config
+        .redaction
+        .redacted(&serde_json::to_value(&resp).unwrap());
+    let redacted = format!(
+        "https://123456789012.dkr.ecr.us-east-1.amazonaws.com/v2/library/ubuntu/tags/list?{REDACTED}"
+    );
+
+    assert_eq!(redacted, resp["headers"]["location"]);
+    assert_eq!(json!([redacted]), resp["multiValueHeaders"]["location"]);
+    assert!(!resp.to_string().contains("SECRET"));
+
+    assert_eq!(
+        redacted,
+        config
+            .redaction
+            .redacted_url("https://123456789012.dkr.ecr.us-east-1.amazonaws.com/v2/library/ubuntu/tags/list?n=10&token=SECRET")
+    );
+
+    // the location is left as is unless query strings are redacted
+    let redaction = Redaction::builder().paths(vec!["cookies".into()]).build();
+    assert_eq!(
+        "https://example.com/v2/?token=SECRET",
+        redaction.redacted_url("https://example.com/v2/?token=SECRET")
+    );
+    assert_eq!(
+        "https://example.com/v2/",
+        Redaction::default().redacted_url("https://example.com/v2/")
+    );
+}
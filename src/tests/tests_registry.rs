@@ -0,0 +1,101 @@
+use crate::registry::{Endpoint, EndpointKind, Reference};
+
+#[test]
+fn test_reference_parse() {
+    assert_eq!(Reference::Tag("latest".into()), Reference::parse("latest"));
+    assert_eq!(
+        Reference::Digest("sha256:abc123".into()),
+        Reference::parse("sha256:abc123")
+    );
+    assert_eq!(
+        Reference::Digest("sha256+b64u:LCa0a2j_xo_5m0U8HTBBNBNCLXBkg7-g-YpeiGJm564=".into()),
+        Reference::parse("sha256+b64u:LCa0a2j_xo_5m0U8HTBBNBNCLXBkg7-g-YpeiGJm564=")
+    );
+
+    // neither side of the separator may be empty or contain invalid characters
+    assert!(!Reference::parse("sha256:").is_digest());
+    assert!(!Reference::parse(":abc").is_digest());
+    assert!(!Reference::parse("SHA256:abc").is_digest());
+}
+
+#[test]
+fn test_endpoint_parse() {
+    assert_eq!(Endpoint::Base, Endpoint::parse("/v2/"));
+    assert_eq!(Endpoint::Base, Endpoint::parse("/v2"));
+    assert_eq!(Endpoint::Catalog, Endpoint::parse("/v2/_catalog"));
+    assert_eq!(
+        Endpoint::Tags {
+            repository: "library/ubuntu".into()
+        },
+        Endpoint::parse("/v2/library/ubuntu/tags/list")
+    );
+    assert_eq!(
+        Endpoint::Manifest {
+            repository: "a/b/c".into(),
+            reference: Reference::Tag("1.0".into()),
+        },
+        Endpoint::parse("/v2/a/b/c/manifests/1.0")
+    );
+    assert_eq!(
+        Endpoint::Blob {
+            repository: "ubuntu".into(),
+            digest: "sha256:abc".into(),
+        },
+        Endpoint::parse("/v2/ubuntu/blobs/sha256:abc")
+    );
+    assert_eq!(
+        Endpoint::BlobUpload {
+            repository: "ubuntu".into()
+        },
+        Endpoint::parse("/v2/ubuntu/blobs/uploads/")
+    );
+    assert_eq!(
+        Endpoint::BlobUpload {
+            repository: "ubuntu".into()
+        },
+        Endpoint::parse("/v2/ubuntu/blobs/uploads/some-uuid")
+    );
+    assert_eq!(
+        Endpoint::Referrers {
+            repository: "ubuntu".into(),
+            digest: "sha256:abc".into(),
+        },
+        Endpoint::parse("/v2/ubuntu/referrers/sha256:abc")
+    );
+
+    // routes without a repository and paths outside of the API
+    assert_eq!(Endpoint::Other, Endpoint::parse("/v2/manifests/latest"));
+    assert_eq!(Endpoint::Other, Endpoint::parse("/v2/ubuntu"));
+    assert_eq!(Endpoint::Other, Endpoint::parse("/"));
+    assert_eq!(Endpoint::Other, Endpoint::parse("/v2x/ubuntu/tags/list"));
+}
+
+#[test]
+fn test_endpoint_accessors() {
+    let endpoint = Endpoint::parse("/v2/library/ubuntu/manifests/sha256:abc");
+
+    assert_eq!(EndpointKind::Manifest, endpoint.kind());
+    assert_eq!(Some("library/ubuntu"), endpoint.repository());
+    assert_eq!(
+        Some(&Reference::Digest("sha256:abc".into())),
+        endpoint.reference()
+    );
+    assert!(endpoint.is_content_addressed());
+
+    assert!(!Endpoint::parse("/v2/ubuntu/manifests/latest").is_content_addressed());
+    assert!(Endpoint::parse("/v2/ubuntu/blobs/sha256:abc").is_content_addressed());
+    // a blob path whose last segment is not a digest may address anything
+    assert!(!Endpoint::parse("/v2/ubuntu/blobs/latest").is_content_addressed());
+    assert_eq!("blob_upload", EndpointKind::BlobUpload.to_string());
+
+    assert_eq!(Some("library/ubuntu@sha256:abc".into()), endpoint.image());
+    assert_eq!(
+        Some("ubuntu:latest".into()),
+        Endpoint::parse("/v2/ubuntu/manifests/latest").image()
+    );
+    assert_eq!(
+        Some("ubuntu".into()),
+        Endpoint::parse("/v2/ubuntu/tags/list").image()
+    );
+    assert_eq!(None, Endpoint::parse("/v2/_catalog").image());
+}
@@ -0,0 +1,271 @@
+use crate::config::{
+    ALLOWED_REPOSITORIES_ENV_VAR, Config, ConfigError, ConfigFormat, DENIED_REPOSITORIES_ENV_VAR,
+    DENIED_RESPONSE_ENV_VAR, ECR_REGISTRY_ENV_VAR,
+};
+use crate::error::RewriteError;
+use crate::oci::{ErrorCode, ErrorResponse};
+use crate::registry::Endpoint;
+use crate::repositories::{DeniedResponse, Pattern, RepositoryPolicy, RepositoryRule};
+use crate::requests::ApiGatewayRequestType;
+use crate::rewriter::{Decision, Rewriter};
+use aws_lambda_events::encodings::Body;
+
+const DIGEST: &str = "sha256:6c3c624b58dbbcd3c0dd82b4c53f04194d1247c6eebdaab7c610cf7d66709b3b";
+
+fn rules(rules: &[&str]) -> Vec<RepositoryRule> {
+    rules.iter().map(|rule| rule.parse().unwrap()).collect()
+}
+
+#[test]
+fn test_repository_patterns() {
+    for (pattern, value, expected) in [
+        ("library/ubuntu", "library/ubuntu", true),
+        ("library/ubuntu", "library/ubuntu2", false),
+        ("internal/*", "internal/tools", true),
+        ("internal/*", "internal/team/tools", true),
+        ("internal/*", "internal", false),
+        ("*/secret", "team/secret", true),
+        ("*-rc*", "1.2-rc1", true),
+        ("*-rc*", "1.2", false),
+        ("v?", "v1", true),
+        ("v?", "v10", false),
+        ("*a*b", "xaxxbab", true),
+        ("*", "", true),
+        ("regex:team-[a-z]+/.*", "team-red/app", true),
+        ("regex:team-[a-z]+/.*", "team-42/app", false),
+        // regexes must match the whole value
+        ("regex:internal", "not-internal", false),
+    ] {
+        let parsed: Pattern = pattern.parse().unwrap();
+        assert_eq!(expected, parsed.matches(value), "{pattern} against {value}");
+        assert_eq!(pattern, parsed.to_string());
+    }
+
+    assert!("regex:(".parse::<Pattern>().is_err());
+    assert!("".parse::<Pattern>().is_err());
+}
+
+#[test]
+fn test_repository_rules() {
+    let rule: RepositoryRule = "library/ubuntu:*-rc*".parse().unwrap();
+    assert_eq!(Pattern::Glob("library/ubuntu".into()), rule.repository);
+    assert_eq!(Some(Pattern::Glob("*-rc*".into())), rule.tag);
+    assert!(rule.matches("library/ubuntu", Some("24.04-rc1"), false));
+    assert!(!rule.matches("library/ubuntu", Some("24.04"), false));
+
+    // the tag of requests by digest is unknown
+    assert!(!rule.matches("library/ubuntu", None, false));
+    assert!(rule.matches("library/ubuntu", None, true));
+
+    // a regex may contain colons
+    let rule: RepositoryRule = "regex:a(?:b)?".parse().unwrap();
+    assert_eq!(None, rule.tag);
+    assert!(rule.matches("ab", Some("latest"), false));
+}
+
+#[test]
+fn test_repository_policy() {
+    let allows = |policy: &RepositoryPolicy, path: &str| policy.allows(&Endpoint::parse(path));
+
+    // everything is allowed by default
+    let policy = RepositoryPolicy::default();
+    assert!(allows(&policy, "/v2/internal/tools/manifests/latest"));
+
+    let policy = RepositoryPolicy::builder()
+        .deny(rules(&["internal/*", "library/ubuntu:*-rc*"]))
+        .build();
+
+    assert!(!allows(&policy, "/v2/internal/tools/manifests/latest"));
+    assert!(!allows(
+        &policy,
+        &format!("/v2/internal/tools/blobs/{DIGEST}")
+    ));
+    assert!(!allows(&policy, "/v2/internal/tools/tags/list"));
+    assert!(!allows(&policy, "/v2/library/ubuntu/manifests/24.04-rc1"));
+    assert!(allows(&policy, "/v2/library/ubuntu/manifests/24.04"));
+    assert!(allows(
+        &policy,
+        &format!("/v2/library/ubuntu/blobs/{DIGEST}")
+    ));
+    assert!(allows(&policy, "/v2/"));
+    assert!(allows(&policy, "/v2/_catalog"));
+
+    // encoded slashes do not hide a repository from the rules
+    assert!(!allows(&policy, "/v2/internal%2Fsecret/manifests/latest"));
+    assert!(!allows(&policy, "/v2/library%2Fubuntu/manifests/24.04-rc1"));
+    assert!(!allows(&policy, "/v2/library/ubuntu/manifests/24.04%2Drc1"));
+
+    let policy = RepositoryPolicy::builder()
+        .allow(rules(&["library/*", "regex:team-(red|blue)/.+"]))
+        .deny(rules(&["library/secret"]))
+        .build();
+
+    assert!(allows(&policy, "/v2/library/ubuntu/manifests/latest"));
+    assert!(allows(&policy, "/v2/team-red/app/manifests/v1"));
+    assert!(!allows(&policy, "/v2/team-green/app/manifests/v1"));
+    assert!(!allows(&policy, "/v2/other/manifests/latest"));
+    // deny rules take precedence
+    assert!(!allows(&policy, "/v2/library/secret/manifests/latest"));
+    assert!(allows(&policy, "/v2/"));
+}
+
+#[test]
+fn test_repository_policy_config() {
+    let toml = r#"
+        registry_host = "ecr.myhost.com"
+
+        [repositories]
+        allow = ["library/*", { repository = "team/app", tag = "regex:v[0-9]+" }]
+        deny = ["regex:library/(secret|internal)"]
+        denied_response = "name-unknown"
+    "#;
+
+    let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+
+    assert_eq!(
+        RepositoryPolicy::builder()
+            .allow(vec![
+                "library/*".parse().unwrap(),
+                RepositoryRule {
+                    repository: "team/app".parse().unwrap(),
+                    tag: Some("regex:v[0-9]+".parse().unwrap()),
+                },
+            ])
+            .deny(rules(&["regex:library/(secret|internal)"]))
+            .denied_response(DeniedResponse::NameUnknown)
+            .build(),
+        config.repositories
+    );
+
+    // rules survive a round trip
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(config, Config::parse(&json, ConfigFormat::Json).unwrap());
+
+    assert!(Config::parse("[repositories]\ndeny = [\"regex:(\"]", ConfigFormat::Toml).is_err());
+
+    let mut config = Config::default();
+
+    let errors = config.apply_overrides(|name| match name {
+        ALLOWED_REPOSITORIES_ENV_VAR => Some("library/*, team/app:v*".into()),
+        DENIED_REPOSITORIES_ENV_VAR => Some("regex:[".into()),
+        DENIED_RESPONSE_ENV_VAR => Some("404".into()),
+        _ => None,
+    });
+
+    assert!(matches!(
+        &errors[..],
+        [ConfigError::Invalid { key, .. }] if key == DENIED_REPOSITORIES_ENV_VAR
+    ));
+    assert_eq!(
+        rules(&["library/*", "team/app:v*"]),
+        config.repositories.allow
+    );
+    assert!(config.repositories.deny.is_empty());
+    assert_eq!(
+        DeniedResponse::NameUnknown,
+        config.repositories.denied_response
+    );
+}
+
+#[test]
+fn test_rewriter_denied_repositories() {
+    let config = Config::builder()
+        .registry_host("ecr.myhost.com")
+        .repositories(
+            RepositoryPolicy::builder()
+                .deny(rules(&["internal/*"]))
+                .build(),
+        )
+        .build();
+
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/v2/internal/tools/manifests/latest");
+
+    let rewriter = Rewriter::new(config.clone());
+
+    assert_eq!(
+        Decision::Reject(RewriteError::RepositoryDenied("internal/tools".into())),
+        rewriter.route(&req)
+    );
+
+    let resp = rewriter.respond(&req);
+    assert_eq!(403, resp.status_code());
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(ErrorCode::Denied, body.errors[0].code);
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    let mut config = config;
+    config.repositories.denied_response = DeniedResponse::NameUnknown;
+    let rewriter = Rewriter::new(config);
+
+    let resp = rewriter.respond(&req);
+    assert_eq!(404, resp.status_code());
+    assert!(resp.headers().get("Location").is_none());
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(ErrorCode::NameUnknown, body.errors[0].code);
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    // the path is normalized before matching, keeping the encoded slash
+    req.set_path("/v2/internal%2fsecret/manifests/latest");
+    assert_eq!(
+        Decision::Reject(RewriteError::RepositoryUnknown("internal%2Fsecret".into())),
+        rewriter.route(&req)
+    );
+
+    req.set_path("/v2/library/ubuntu/manifests/latest");
+    assert_eq!(307, rewriter.respond(&req).status_code());
+}
+
+#[test]
+fn test_invalid_repository_rules_fail_closed() {
+    let lookup = |name: &str| match name {
+        ECR_REGISTRY_ENV_VAR => Some("123456789012.dkr.ecr.us-east-1.amazonaws.com".into()),
+        DENIED_REPOSITORIES_ENV_VAR => Some("internal/*,regex:(unclosed".into()),
+        _ => None,
+    };
+
+    // ignoring the deny list would redirect requests for the repositories it denies, so invalid
+    // rules are an error even when the configuration is not strict
+    assert!(
+        matches!(Config::default().checked(lookup), Err(e) if e.fails_open()),
+        "invalid rules were ignored"
+    );
+    assert!(Config::load(lookup).is_err());
+
+    // invalid rules in stage variables leave the stage with the environment's rules
+    let config = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .stage_variables(true)
+        .repositories(
+            RepositoryPolicy::builder()
+                .deny(rules(&["internal/*"]))
+                .build(),
+        )
+        .build();
+
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/v2/internal/secret/manifests/latest");
+    req.stage_variables_mut().extend([
+        (ALLOWED_REPOSITORIES_ENV_VAR.into(), "internal/*".into()),
+        (DENIED_REPOSITORIES_ENV_VAR.into(), "regex:(unclosed".into()),
+    ]);
+
+    let (stage_config, errors) = config.for_request(&req);
+    assert_eq!(config, *stage_config);
+    assert!(errors.iter().any(ConfigError::fails_open));
+
+    assert_eq!(
+        Decision::Reject(RewriteError::RepositoryDenied("internal/secret".into())),
+        Rewriter::new(config).route(&req)
+    );
+}
@@ -0,0 +1,420 @@
+use crate::cache::{CacheDirectives, CachePolicy};
+use crate::config::{Config, ECR_REGISTRY_ENV_VAR};
+use crate::error::RewriteError;
+use crate::methods::MethodPolicy;
+use crate::oci::{ErrorCode, ErrorResponse};
+use crate::paths::PathError;
+use crate::problem::Problem;
+use crate::registry::{Endpoint, Reference};
+use crate::requests::ApiGatewayRequestType;
+use crate::responses::ApiGatewayResponseType;
+use crate::rewriter::{Decision, Rewriter, install_panic_hook};
+use crate::source::ReloadingConfig;
+use crate::templates::{Branding, ErrorPages, Template};
+use crate::tests::fixtures::{APIGW_REQ_V1, APIGW_REQ_V2};
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::http::Method;
+use lambda_runtime::Context;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[test]
+fn test_rewriter_route() {
+    let rewriter = Rewriter::new(Config::builder().registry_host("ecr.myhost.com").build());
+
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/v2/library/ubuntu/manifests/latest");
+
+    assert_eq!(
+        Decision::Redirect {
+            location: "https://ecr.myhost.com/v2/library/ubuntu/manifests/latest".into(),
+            endpoint: Endpoint::Manifest {
+                repository: "library/ubuntu".into(),
+                reference: Reference::Tag("latest".into()),
+            },
+        },
+        rewriter.route(&req)
+    );
+
+    req.set_path("/v2/../x");
+    assert_eq!(
+        Decision::Reject(RewriteError::InvalidPath(PathError::DotSegment)),
+        rewriter.route(&req)
+    );
+
+    req.set_method(Method::DELETE);
+    assert_eq!(
+        Decision::Reject(RewriteError::MethodNotAllowed(Method::DELETE)),
+        rewriter.route(&req)
+    );
+
+    req.set_method(Method::GET);
+    assert_eq!(
+        Decision::Reject(RewriteError::Misconfigured),
+        Rewriter::default().route(&req)
+    );
+
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/v2/");
+    req.set_raw_query("a=\n");
+    assert_eq!(
+        Decision::Reject(RewriteError::InvalidLocation),
+        rewriter.route(&req)
+    );
+}
+
+#[test]
+fn test_rewriter_instances() {
+    let a = Rewriter::new(Config::builder().registry_host("a.myhost.com").build());
+    let b = Rewriter::new(
+        Config::builder()
+            .registry_host("b.myhost.com")
+            .allowed_methods(MethodPolicy::read_write())
+            .build(),
+    );
+
+    let mut req = ApiGatewayRequestType::V1(Default::default());
+    req.set_method(Method::PUT);
+    req.set_path("/v2/");
+
+    assert_eq!(405, a.respond(&req).status_code());
+    assert_eq!(
+        "https://b.myhost.com/v2/",
+        b.respond(&req).headers().get("Location").unwrap()
+    );
+}
+
+#[test]
+fn test_rewriter_rewrite() {
+    let rewriter: Rewriter = Config::builder()
+        .registry_host("ecr.myhost.com")
+        .cache_max_age(120)
+        .build()
+        .into();
+
+    let resp = rewriter.rewrite(
+        serde_json::from_str(APIGW_REQ_V2).unwrap(),
+        Context::default(),
+    );
+
+    assert!(resp.is_v2());
+    assert_eq!(307, resp.status_code());
+    assert_eq!(
+        "https://ecr.myhost.com/",
+        resp.headers().get("Location").unwrap()
+    );
+    assert_eq!("max-age=120", resp.headers().get("Cache-Control").unwrap());
+
+    // the v1 fixture is a POST, which the default policy does not allow
+    let resp = rewriter.rewrite(
+        serde_json::from_str(APIGW_REQ_V1).unwrap(),
+        Context::default(),
+    );
+
+    assert!(resp.is_v1());
+    assert_eq!(405, resp.status_code());
+
+    // without a registry host, an error is returned
+    let resp = Rewriter::default().rewrite(
+        serde_json::from_str(APIGW_REQ_V2).unwrap(),
+        Context::default(),
+    );
+
+    assert_eq!(500, resp.status_code());
+
+    // unrecognized events are answered with a v1 error
+    let resp = rewriter.rewrite(serde_json::json!("nope"), Context::default());
+
+    assert!(resp.is_v1());
+    assert_eq!(500, resp.status_code());
+}
+
+#[test]
+fn test_rewriter_stage_variables() {
+    let rewriter = Rewriter::new(
+        Config::builder()
+            .registry_host("111111111111.dkr.ecr.us-east-1.amazonaws.com")
+            .stage_variables(true)
+            .build(),
+    );
+
+    let mut dev = ApiGatewayRequestType::V1(Default::default());
+    dev.set_path("/v2/");
+    dev.stage_variables_mut().insert(
+        ECR_REGISTRY_ENV_VAR.to_string(),
+        "222222222222.dkr.ecr.us-west-2.amazonaws.com".to_string(),
+    );
+
+    let mut prod = dev.clone();
+    prod.stage_variables_mut().insert(
+        ECR_REGISTRY_ENV_VAR.to_string(),
+        "333333333333.dkr.ecr.eu-west-1.amazonaws.com".to_string(),
+    );
+
+    assert_eq!(
+        "https://222222222222.dkr.ecr.us-west-2.amazonaws.com/v2/",
+        rewriter.respond(&dev).headers().get("Location").unwrap()
+    );
+    assert_eq!(
+        "https://333333333333.dkr.ecr.eu-west-1.amazonaws.com/v2/",
+        rewriter.respond(&prod).headers().get("Location").unwrap()
+    );
+
+    // a stage variable cannot point redirects at a host which is not an ECR registry
+    let mut rogue = dev.clone();
+    rogue.stage_variables_mut().insert(
+        ECR_REGISTRY_ENV_VAR.to_string(),
+        "attacker.example.com".to_string(),
+    );
+
+    assert_eq!(
+        "https://111111111111.dkr.ecr.us-east-1.amazonaws.com/v2/",
+        rewriter.respond(&rogue).headers().get("Location").unwrap()
+    );
+}
+
+#[test]
+fn test_rewriter_cache_control() {
+    let rewriter = Rewriter::new(
+        Config::builder()
+            .registry_host("ecr.myhost.com")
+            .cache(
+                CachePolicy::builder()
+                    .tag(
+                        CacheDirectives::builder()
+                            .public(true)
+                            .max_age(30)
+                            .s_maxage(300)
+                            .stale_while_revalidate(60)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build(),
+    );
+
+    let cache_control = |path: &str| {
+        let mut req = ApiGatewayRequestType::V1(Default::default());
+        req.set_path(path);
+
+        rewriter
+            .respond(&req)
+            .headers()
+            .get("Cache-Control")
+            .map(|v| v.to_str().unwrap().to_string())
+    };
+
+    assert_eq!(
+        Some("max-age=31536000, immutable".into()),
+        cache_control("/v2/library/ubuntu/manifests/sha256:abc123")
+    );
+    assert_eq!(
+        Some("max-age=31536000, immutable".into()),
+        cache_control("/v2/library/ubuntu/blobs/sha256:abc123")
+    );
+    assert_eq!(
+        Some("public, max-age=30, s-maxage=300, stale-while-revalidate=60".into()),
+        cache_control("/v2/library/ubuntu/manifests/latest")
+    );
+    assert_eq!(Some("no-store".into()), cache_control("/v2/_catalog"));
+    assert_eq!(
+        Some("no-store".into()),
+        cache_control("/v2/library/ubuntu/tags/list")
+    );
+    assert_eq!(Some("max-age=60".into()), cache_control("/v2/"));
+
+    // errors are never cached
+    assert_eq!(Some("no-store".into()), cache_control("/v2/../x"));
+}
+
+#[test]
+fn test_rewriter_problem_json() {
+    let rewriter = Rewriter::new(Config::builder().registry_host("ecr.myhost.com").build());
+
+    let mut event = serde_json::from_str::<serde_json::Value>(APIGW_REQ_V2).unwrap();
+    event["rawPath"] = "/v2/%2e%2e/x".into();
+    event["headers"]["accept"] = "application/problem+json".into();
+
+    let mut ctx = Context::default();
+    ctx.request_id = "abc-123".into();
+
+    let resp = rewriter.rewrite(event.clone(), ctx);
+
+    assert_eq!(400, resp.status_code());
+    assert_eq!(
+        "application/problem+json",
+        resp.headers().get("Content-Type").unwrap()
+    );
+
+    let problem: Problem = match resp.body() {
+        Some(Body::Text(body)) => serde_json::from_str(body).unwrap(),
+        _ => panic!("returned non-text body"),
+    };
+
+    assert_eq!(400, problem.status);
+    assert_eq!("Invalid Path", problem.title);
+    assert_eq!(Some("abc-123".into()), problem.instance);
+
+    // registry clients get the OCI format by default
+    event["headers"]["accept"] = "*/*".into();
+
+    let resp = rewriter.rewrite(event, Context::default());
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(ErrorCode::NameInvalid, body.errors[0].code);
+        }
+        _ => panic!("returned non-text body"),
+    }
+}
+
+#[test]
+fn test_rewriter_panic_free() {
+    install_panic_hook();
+
+    let fetches = AtomicUsize::new(0);
+
+    // a source which panics on every fetch after the first
+    let source = move || {
+        if fetches.fetch_add(1, Ordering::SeqCst) > 0 {
+            panic!("source exploded");
+        }
+
+        Ok(Config::builder().registry_host("ecr.myhost.com").build())
+    };
+
+    let rewriter = Rewriter::reloading(ReloadingConfig::new(source, Duration::ZERO).unwrap());
+
+    let mut event: serde_json::Value = serde_json::from_str(APIGW_REQ_V2).unwrap();
+    event["headers"]["accept"] = "application/json".into();
+
+    let resp = rewriter.rewrite(event, Context::default());
+
+    // the request is still answered in its own version
+    assert_eq!(500, resp.status_code());
+    assert!(matches!(resp, ApiGatewayResponseType::V2(_)));
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(ErrorCode::Unknown, body.errors[0].code);
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    // a panic does not poison the rewriter
+    let resp = rewriter.rewrite(
+        serde_json::from_str(APIGW_REQ_V2).unwrap(),
+        Context::default(),
+    );
+
+    assert_eq!(500, resp.status_code());
+}
+
+#[test]
+fn test_rewriter_branded_errors() {
+    let rewriter = Rewriter::new(
+        Config::builder()
+            .error_pages(
+                ErrorPages::builder()
+                    .domains(BTreeMap::from([(
+                        "docker.team.com".to_string(),
+                        Branding::builder()
+                            .html(
+                                Template::parse(
+                                    "<p>{{image}} failed: {{message}} ({{request_id}})</p>",
+                                )
+                                .unwrap(),
+                            )
+                            .build(),
+                    )]))
+                    .build(),
+            )
+            .build(),
+    );
+
+    let mut event = serde_json::from_str::<serde_json::Value>(APIGW_REQ_V2).unwrap();
+    event["rawPath"] = "/v2/library/ubuntu/manifests/latest".into();
+    event["headers"]["accept"] = "text/html".into();
+    event["requestContext"]["domainName"] = "docker.team.com".into();
+
+    let mut ctx = Context::default();
+    ctx.request_id = "abc-123".into();
+
+    let resp = rewriter.rewrite(event.clone(), ctx);
+
+    assert_eq!(500, resp.status_code());
+
+    match resp.body() {
+        Some(Body::Text(body)) => assert_eq!(
+            "<p>library/ubuntu:latest failed: Destination host name not set. (abc-123)</p>",
+            body
+        ),
+        _ => panic!("returned non-text body"),
+    }
+
+    // other domains get the default page
+    event["requestContext"]["domainName"] = "docker.other.com".into();
+
+    match rewriter.rewrite(event, Context::default()).body() {
+        Some(Body::Text(body)) => assert!(body.starts_with("<!doctype html>")),
+        _ => panic!("returned non-text body"),
+    }
+}
+
+#[test]
+fn test_rewriter_request_ids() {
+    let mut event = serde_json::from_str::<serde_json::Value>(APIGW_REQ_V2).unwrap();
+    event["requestContext"]["requestId"] = "api-id".into();
+    event["headers"]["x-amzn-trace-id"] = "Root=1-abc".into();
+
+    let mut ctx = Context::default();
+    ctx.request_id = "lambda-id".into();
+
+    // on redirects
+    let rewriter = Rewriter::new(Config::builder().registry_host("ecr.myhost.com").build());
+    let resp = rewriter.rewrite(event.clone(), ctx.clone());
+
+    assert_eq!(307, resp.status_code());
+    assert_eq!("lambda-id", resp.headers().get("X-Request-Id").unwrap());
+    assert_eq!("api-id", resp.headers().get("X-Api-Request-Id").unwrap());
+    assert_eq!("Root=1-abc", resp.headers().get("X-Amzn-Trace-Id").unwrap());
+
+    // and errors, in their bodies as well
+    let resp = Rewriter::default().rewrite(event.clone(), ctx.clone());
+
+    assert_eq!(500, resp.status_code());
+    assert_eq!("lambda-id", resp.headers().get("X-Request-Id").unwrap());
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            assert!(body.contains("Request ID: lambda-id"));
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    event["headers"]["accept"] = "application/json".into();
+
+    match Rewriter::default().rewrite(event, ctx.clone()).body() {
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+
+            assert_eq!(
+                Some(serde_json::json!({
+                    "request_id": "lambda-id",
+                    "api_request_id": "api-id",
+                    "trace_id": "Root=1-abc",
+                })),
+                body.errors[0].detail
+            );
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    // even for invalid events
+    let resp = rewriter.rewrite(serde_json::json!("nope"), ctx);
+
+    assert_eq!("lambda-id", resp.headers().get("X-Request-Id").unwrap());
+}
@@ -0,0 +1,232 @@
+use crate::config::{Config, ConfigError};
+use crate::registry::Endpoint;
+use crate::requests::ApiGatewayRequestType;
+use crate::rewriter::{Decision, Rewriter};
+use crate::source::{ConfigSource, FileConfigSource, ReloadingConfig};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+/// Utility: a config source which yields a different registry host on every fetch, failing on
+/// the given fetch numbers
+fn counting_source(fail_on: &'static [usize]) -> (Arc<AtomicUsize>, impl ConfigSource + 'static) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let fetches = count.clone();
+
+    let source = move || {
+        let n = fetches.fetch_add(1, Ordering::SeqCst);
+
+        if fail_on.contains(&n) {
+            Err(ConfigError::invalid("test", "fetch failed"))
+        } else {
+            Ok(Config::builder()
+                .registry_host(format!("r{n}.myhost.com"))
+                .build())
+        }
+    };
+
+    (count, source)
+}
+
+#[test]
+fn test_reloading_config_ttl() {
+    let (count, source) = counting_source(&[]);
+    let config = ReloadingConfig::new(source, Duration::from_secs(3600)).unwrap();
+
+    assert_eq!(
+        Some("r0.myhost.com"),
+        config.current().registry_host.as_deref()
+    );
+    assert_eq!(
+        Some("r0.myhost.com"),
+        config.current().registry_host.as_deref()
+    );
+    assert_eq!(1, count.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_reloading_config_refresh() {
+    let (_, source) = counting_source(&[2]);
+    let config = ReloadingConfig::new(source, Duration::ZERO).unwrap();
+
+    assert_eq!(
+        Some("r1.myhost.com"),
+        config.current().registry_host.as_deref()
+    );
+    // the failed fetch keeps the last known good configuration
+    assert_eq!(
+        Some("r1.myhost.com"),
+        config.current().registry_host.as_deref()
+    );
+    assert_eq!(
+        Some("r3.myhost.com"),
+        config.current().registry_host.as_deref()
+    );
+}
+
+#[test]
+fn test_reloading_config_fetches_outside_lock() {
+    let (fetching, fetch_started) = mpsc::sync_channel(1);
+    let (release, released) = mpsc::channel::<()>();
+    let released = Mutex::new(released);
+    let fetches = AtomicUsize::new(0);
+
+    // a source whose reload blocks until released
+    let source = move || {
+        if fetches.fetch_add(1, Ordering::SeqCst) > 0 {
+            fetching.send(()).unwrap();
+            released.lock().recv().unwrap();
+        }
+
+        Ok(Config::builder().registry_host("ecr.myhost.com").build())
+    };
+
+    let config = Arc::new(ReloadingConfig::new(source, Duration::from_millis(100)).unwrap());
+    thread::sleep(Duration::from_millis(150));
+
+    let reloading = thread::spawn({
+        let config = config.clone();
+        move || config.current()
+    });
+
+    fetch_started.recv().unwrap();
+
+    // other callers keep using the current configuration while the reload is in progress
+    let (done, current) = mpsc::channel();
+
+    thread::spawn({
+        let config = config.clone();
+        move || done.send(config.current()).unwrap()
+    });
+
+    let current = current.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(Some("ecr.myhost.com"), current.registry_host.as_deref());
+
+    release.send(()).unwrap();
+    reloading.join().unwrap();
+}
+
+#[test]
+fn test_reloading_config_initial_failure() {
+    let (_, source) = counting_source(&[0]);
+
+    assert!(ReloadingConfig::new(source, Duration::ZERO).is_err());
+}
+
+#[test]
+fn test_reloading_config_unchanged() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let fetches = count.clone();
+
+    let source = move || {
+        fetches.fetch_add(1, Ordering::SeqCst);
+        Ok(Config::builder().registry_host("a.myhost.com").build())
+    };
+
+    let config = ReloadingConfig::new(source, Duration::ZERO).unwrap();
+    let first = config.current();
+
+    // refetching an identical configuration keeps the existing one
+    assert!(Arc::ptr_eq(&first, &config.current()));
+    assert_eq!(3, count.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_reloading_config_invalid() {
+    /// Utility: a source validating the configuration it yields, as [WithEnvOverrides](crate::source::WithEnvOverrides) does
+    struct Validating(Arc<Mutex<Config>>);
+
+    impl ConfigSource for Validating {
+        fn fetch(&self) -> Result<Config, ConfigError> {
+            Ok(self.0.lock().clone())
+        }
+
+        fn fetch_checked(&self) -> Result<(Config, Vec<ConfigError>), ConfigError> {
+            let config = self.fetch()?;
+            let errors = config.validate();
+
+            Ok((config, errors))
+        }
+    }
+
+    let good = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .build();
+
+    let source = Arc::new(Mutex::new(good.clone()));
+    let config = ReloadingConfig::new(Validating(source.clone()), Duration::ZERO).unwrap();
+
+    // a configuration without a registry host would fail every request
+    *source.lock() = Config::default();
+    assert_eq!(good, *config.current());
+
+    *source.lock() = Config::builder()
+        .registry_host("attacker.example.com")
+        .build();
+    assert_eq!(good, *config.current());
+
+    // valid configuration is picked up again
+    let next = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-west-2.amazonaws.com")
+        .build();
+    *source.lock() = next.clone();
+    assert_eq!(next, *config.current());
+
+    // problems the current configuration already has do not hold back a reload
+    *source.lock() = Config::default();
+    let config = ReloadingConfig::new(Validating(source.clone()), Duration::ZERO).unwrap();
+
+    let next = Config::builder().cache_max_age(60).build();
+    *source.lock() = next.clone();
+    assert_eq!(next, *config.current());
+}
+
+#[test]
+fn test_file_config_source() {
+    let path = std::env::temp_dir().join(format!(
+        "lambda-ecr-rewrite-{}-test-file-config-source.toml",
+        std::process::id()
+    ));
+
+    std::fs::write(&path, "registry_host = \"a.myhost.com\"\n").unwrap();
+
+    let rewriter = Rewriter::reloading(
+        ReloadingConfig::new(FileConfigSource::new(&path), Duration::ZERO).unwrap(),
+    );
+
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/v2/");
+
+    assert_eq!(
+        Decision::Redirect {
+            location: "https://a.myhost.com/v2/".into(),
+            endpoint: Endpoint::Base,
+        },
+        rewriter.route(&req)
+    );
+
+    std::fs::write(&path, "registry_host = \"b.myhost.com\"\n").unwrap();
+
+    assert_eq!(
+        Decision::Redirect {
+            location: "https://b.myhost.com/v2/".into(),
+            endpoint: Endpoint::Base,
+        },
+        rewriter.route(&req)
+    );
+
+    // a broken file keeps the previous configuration
+    std::fs::write(&path, "registry_host = \n").unwrap();
+
+    assert_eq!(
+        Decision::Redirect {
+            location: "https://b.myhost.com/v2/".into(),
+            endpoint: Endpoint::Base,
+        },
+        rewriter.route(&req)
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,359 @@
+use crate::config::{
+    Config, ConfigError, OTEL_ENDPOINT_ENV_VAR, OTEL_SERVICE_NAME_ENV_VAR, OTEL_TIMEOUT_ENV_VAR,
+    OTEL_TRACES_ENDPOINT_ENV_VAR,
+};
+use crate::rewriter::Rewriter;
+use crate::telemetry::{DEFAULT_SERVICE_NAME, ExportError, Telemetry, TracingConfig};
+use crate::tests::fixtures::APIGW_REQ_V2;
+use aws_lambda_events::http::{HeaderMap, HeaderValue};
+use lambda_runtime::Context;
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+/// Utility: read an OTLP request from a client, respond with success, and return the request's
+/// head and body
+fn receive(stream: TcpStream) -> (Vec<String>, Value) {
+    let mut reader = BufReader::new(stream);
+    let mut head = vec![];
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        if line.trim().is_empty() {
+            break;
+        }
+
+        head.push(line.trim().to_string());
+    }
+
+    let length = head
+        .iter()
+        .find_map(|line| {
+            line.split_once(':')
+                .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        })
+        .unwrap()
+        .1
+        .trim()
+        .parse()
+        .unwrap();
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    reader
+        .get_mut()
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    (head, serde_json::from_slice::<Value>(&body).unwrap())
+}
+
+/// Utility: the spans of an OTLP request
+fn spans(payload: &Value) -> Vec<Value> {
+    payload["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap()
+        .clone()
+}
+
+/// Utility: headers carrying the given traceparent
+fn traceparent_headers(traceparent: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("traceparent", HeaderValue::from_str(traceparent).unwrap());
+    headers
+}
+
+#[test]
+fn test_trace_propagation() {
+    let config = TracingConfig::builder()
+        .endpoint("http://localhost:4318/v1/traces")
+        .build();
+
+    let telemetry = Telemetry::default();
+
+    // nothing is recorded without an endpoint
+    let trace = telemetry.start(
+        &TracingConfig::default(),
+        &traceparent_headers(TRACEPARENT),
+        UNIX_EPOCH,
+    );
+    assert!(!trace.is_enabled());
+    assert_eq!(None, trace.traceparent());
+    assert_eq!(7, trace.in_span("route", || 7));
+
+    // a trace continues that of the incoming traceparent, passing on its sampled flag
+    let trace = telemetry.start(&config, &traceparent_headers(TRACEPARENT), UNIX_EPOCH);
+    let traceparent = trace.traceparent().unwrap();
+    assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    assert!(traceparent.ends_with("-01"));
+    assert!(!traceparent.contains("00f067aa0ba902b7"));
+
+    let trace = telemetry.start(
+        &config,
+        &traceparent_headers("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"),
+        UNIX_EPOCH,
+    );
+    let traceparent = trace.traceparent().unwrap();
+    assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    assert!(traceparent.ends_with("-00"));
+
+    // an invalid traceparent starts a new, sampled trace
+    for invalid in [
+        "00-garbage",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+    ] {
+        let trace = telemetry.start(&config, &traceparent_headers(invalid), UNIX_EPOCH);
+        let traceparent = trace.traceparent().unwrap();
+        assert!(!traceparent.contains("4bf92f3577b34da6a3ce929d0e0e4736"));
+        assert!(traceparent.ends_with("-01"), "{invalid:?}");
+    }
+}
+
+#[test]
+fn test_tracing_export() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+
+    let collector = thread::spawn(move || receive(listener.accept().unwrap().0));
+
+    let rewriter: Rewriter = Config::builder()
+        .registry_host("ecr.myhost.com")
+        .tracing(
+            TracingConfig::builder()
+                .endpoint(endpoint)
+                .service_name("registry")
+                .timeout(5000)
+                .build(),
+        )
+        .build()
+        .into();
+
+    let mut event: Value = serde_json::from_str(APIGW_REQ_V2).unwrap();
+    event["headers"]["traceparent"] = TRACEPARENT.into();
+
+    let mut ctx = Context::default();
+    ctx.request_id = "lambda-id".into();
+
+    assert_eq!(307, rewriter.rewrite(event, ctx).status_code());
+    rewriter.flush_spans();
+
+    let (head, payload) = collector.join().unwrap();
+    assert_eq!("POST /v1/traces HTTP/1.1", head[0]);
+
+    assert!(
+        payload["resourceSpans"][0]["resource"]["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({"key": "service.name", "value": {"stringValue": "registry"}}))
+    );
+
+    let spans = spans(&payload);
+
+    assert_eq!(
+        vec!["parse event", "route", "build response", "GET"],
+        spans
+            .iter()
+            .map(|span| span["name"].as_str().unwrap())
+            .collect::<Vec<_>>()
+    );
+
+    let server = &spans[3];
+    assert_eq!(json!("00f067aa0ba902b7"), server["parentSpanId"]);
+    assert_eq!(json!(2), server["kind"]);
+
+    for span in &spans[..3] {
+        assert_eq!(json!("4bf92f3577b34da6a3ce929d0e0e4736"), span["traceId"]);
+        assert_eq!(server["spanId"], span["parentSpanId"]);
+    }
+
+    let attribute = |key: &str| {
+        server["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|attribute| attribute["key"] == key)
+            .map(|attribute| attribute["value"].clone())
+    };
+
+    assert_eq!(
+        Some(json!({"stringValue": "lambda-id"})),
+        attribute("faas.invocation_id")
+    );
+    assert_eq!(
+        Some(json!({"stringValue": "redirect"})),
+        attribute("lambda_ecr_rewrite.decision")
+    );
+    assert_eq!(
+        Some(json!({"intValue": "307"})),
+        attribute("http.response.status_code")
+    );
+    assert_eq!(None, attribute("error.type"));
+}
+
+#[test]
+fn test_tracing_unsampled() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+
+    let collector = thread::spawn(move || receive(listener.accept().unwrap().0));
+
+    let rewriter: Rewriter = Config::builder()
+        .registry_host("ecr.myhost.com")
+        .tracing(
+            TracingConfig::builder()
+                .endpoint(endpoint)
+                .timeout(5000)
+                .build(),
+        )
+        .build()
+        .into();
+
+    // an upstream sampler dropped the first trace, so only the second is exported
+    for traceparent in [
+        "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00",
+        TRACEPARENT,
+    ] {
+        let mut event: Value = serde_json::from_str(APIGW_REQ_V2).unwrap();
+        event["headers"]["traceparent"] = traceparent.into();
+
+        assert_eq!(
+            307,
+            rewriter.rewrite(event, Context::default()).status_code()
+        );
+    }
+
+    rewriter.flush_spans();
+
+    let spans = spans(&collector.join().unwrap().1);
+    assert_eq!(4, spans.len());
+    assert!(
+        spans
+            .iter()
+            .all(|span| span["traceId"] == json!("4bf92f3577b34da6a3ce929d0e0e4736"))
+    );
+}
+
+#[test]
+fn test_tracing_export_failure() {
+    // nothing is listening, which must not affect the response
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+    drop(listener);
+
+    let config = TracingConfig::builder()
+        .endpoint(endpoint)
+        .timeout(100)
+        .build();
+
+    let telemetry = Telemetry::default();
+    telemetry
+        .start(&config, &HeaderMap::new(), UNIX_EPOCH)
+        .finish("GET", vec![], false);
+
+    // failures are reported after the fact
+    assert!(telemetry.flush().is_err());
+    assert!(matches!(
+        telemetry.take_error(),
+        Some(ExportError::Export(_))
+    ));
+    assert!(telemetry.take_error().is_none());
+
+    let rewriter: Rewriter = Config::builder()
+        .registry_host("ecr.myhost.com")
+        .tracing(config)
+        .build()
+        .into();
+
+    let resp = rewriter.rewrite(
+        serde_json::from_str(APIGW_REQ_V2).unwrap(),
+        Context::default(),
+    );
+
+    assert_eq!(307, resp.status_code());
+}
+
+#[test]
+fn test_tracing_export_in_background() {
+    // the endpoint accepts connections, but never responds
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+
+    let rewriter: Rewriter = Config::builder()
+        .registry_host("ecr.myhost.com")
+        .tracing(
+            TracingConfig::builder()
+                .endpoint(endpoint)
+                .timeout(2000)
+                .build(),
+        )
+        .build()
+        .into();
+
+    let start = Instant::now();
+
+    for _ in 0..3 {
+        let resp = rewriter.rewrite(
+            serde_json::from_str(APIGW_REQ_V2).unwrap(),
+            Context::default(),
+        );
+        assert_eq!(307, resp.status_code());
+    }
+
+    // responses do not wait for the endpoint
+    assert!(start.elapsed() < Duration::from_secs(1));
+    drop(listener);
+}
+
+#[test]
+fn test_tracing_config() {
+    let mut config = Config::default();
+    assert_eq!(None, config.tracing.endpoint);
+    assert_eq!(DEFAULT_SERVICE_NAME, config.tracing.service_name);
+
+    let errors = config.apply_overrides(|name| match name {
+        OTEL_ENDPOINT_ENV_VAR => Some("http://localhost:4318/".into()),
+        OTEL_SERVICE_NAME_ENV_VAR => Some("registry".into()),
+        OTEL_TIMEOUT_ENV_VAR => Some("250".into()),
+        _ => None,
+    });
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        Some("http://localhost:4318/v1/traces"),
+        config.tracing.endpoint.as_deref()
+    );
+    assert_eq!("registry", config.tracing.service_name);
+    assert_eq!(250, config.tracing.timeout);
+    assert_eq!(None, config.tracing.invalid_endpoint());
+
+    config.tracing.endpoint = Some("https://collector.example.com/v1/traces".into());
+    assert_eq!(None, config.tracing.invalid_endpoint());
+
+    // the traces endpoint is used as-is and takes precedence
+    let errors = config.apply_overrides(|name| match name {
+        OTEL_ENDPOINT_ENV_VAR => Some("http://localhost:4318".into()),
+        OTEL_TRACES_ENDPOINT_ENV_VAR => Some("collector.example.com/traces".into()),
+        OTEL_TIMEOUT_ENV_VAR => Some("soon".into()),
+        _ => None,
+    });
+
+    assert!(matches!(
+        &errors[..],
+        [ConfigError::Invalid { key, .. }] if key == OTEL_TIMEOUT_ENV_VAR
+    ));
+    assert_eq!(
+        Some("collector.example.com/traces"),
+        config.tracing.endpoint.as_deref()
+    );
+    assert!(config.validate().iter().any(
+        |e| matches!(e, ConfigError::Invalid { key, .. } if key == OTEL_TRACES_ENDPOINT_ENV_VAR)
+    ));
+}
@@ -0,0 +1,183 @@
+use crate::config::{Config, ConfigError, ConfigFormat};
+use crate::correlation::RequestIds;
+use crate::error::RewriteError;
+use crate::templates::{
+    Branding, DEFAULT_HTML_TEMPLATE, ErrorContext, ErrorPages, Template, TemplateError,
+    TemplateValues,
+};
+use crate::{escape_html, escape_json};
+use std::collections::BTreeMap;
+
+#[test]
+fn test_template_parse() {
+    assert!(Template::parse(DEFAULT_HTML_TEMPLATE).is_ok());
+    assert!(Template::parse("no placeholders").is_ok());
+    assert!(Template::parse("{{ status }} {{message}}").is_ok());
+
+    assert_eq!(
+        Err(TemplateError::UnknownPlaceholder("stauts".into())),
+        Template::parse("{{stauts}}")
+    );
+    assert_eq!(
+        Err(TemplateError::Unterminated),
+        Template::parse("{{status")
+    );
+}
+
+#[test]
+fn test_template_render() {
+    let context = ErrorContext {
+        ids: RequestIds {
+            request_id: Some("abc-123".into()),
+            ..Default::default()
+        },
+        host: Some("docker.example.com".into()),
+        image: Some("library/ubuntu:latest".into()),
+        branding: Branding::builder()
+            .support_link("https://example.com/help")
+            .build(),
+    };
+
+    let values = TemplateValues::new(&RewriteError::Misconfigured, &context);
+
+    let template = Template::parse(
+        "{{status}} {{reason}} [{{code}}] {{title}}: {{message}} ({{request_id}}) \
+        {{host}}/{{image}} {{support_link}}",
+    )
+    .unwrap();
+
+    assert_eq!(
+        "500 Internal Server Error [misconfigured] Registry Not Configured: Destination host name \
+        not set. (abc-123) docker.example.com/library/ubuntu:latest https://example.com/help",
+        template.render(&values, str::to_string)
+    );
+
+    // links which are not http(s) or mailto URLs are never rendered
+    let context = ErrorContext {
+        branding: Branding::builder()
+            .support_link("javascript:alert(document.cookie)")
+            .build(),
+        ..context
+    };
+
+    assert_eq!(
+        "",
+        TemplateValues::new(&RewriteError::Misconfigured, &context).support_link
+    );
+
+    // values are escaped, the template is not
+    let values = TemplateValues {
+        message: "<\"quoted\">".into(),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        "<p>&lt;&quot;quoted&quot;&gt;</p>",
+        Template::parse("<p>{{message}}</p>")
+            .unwrap()
+            .render(&values, escape_html)
+    );
+    assert_eq!(
+        r#"{"m": "<\"quoted\">"}"#,
+        Template::parse(r#"{"m": "{{message}}"}"#)
+            .unwrap()
+            .render(&values, escape_json)
+    );
+}
+
+#[test]
+fn test_error_pages_branding() {
+    let pages = ErrorPages::builder()
+        .default(
+            Branding::builder()
+                .support_link("https://example.com/help")
+                .html(Template::parse("default").unwrap())
+                .build(),
+        )
+        .domains(BTreeMap::from([(
+            "docker.team.com".to_string(),
+            Branding::builder()
+                .html(Template::parse("team").unwrap())
+                .build(),
+        )]))
+        .build();
+
+    let branding = pages.branding(Some("Docker.Team.com:443"));
+
+    assert_eq!(Some("team"), branding.html.as_ref().map(Template::as_str));
+    assert_eq!(
+        Some("https://example.com/help"),
+        branding.support_link.as_deref()
+    );
+
+    for host in [Some("docker.other.com"), None] {
+        assert_eq!(
+            Some("default"),
+            pages.branding(host).html.as_ref().map(Template::as_str)
+        );
+    }
+}
+
+#[test]
+fn test_error_pages_config() {
+    let config = Config::parse(
+        r#"
+        [error_pages.default]
+        support_link = "mailto:help@example.com"
+        html = "<p>{{message}}</p>"
+
+        [error_pages.domains."docker.team.com"]
+        json = '{"errors": [{"code": "UNKNOWN", "message": "{{message}} ({{request_id}})"}]}'
+        "#,
+        ConfigFormat::Toml,
+    )
+    .unwrap();
+
+    assert!(config.error_pages.domains.contains_key("docker.team.com"));
+    assert!(
+        config.validate().iter().all(
+            |e| !matches!(e, ConfigError::Invalid { key, .. } if key.starts_with("error_pages"))
+        )
+    );
+
+    // templates are checked when parsed
+    assert!(matches!(
+        Config::parse(
+            "[error_pages.default]\nhtml = \"{{nope}}\"",
+            ConfigFormat::Toml
+        ),
+        Err(ConfigError::Parse(None, _))
+    ));
+
+    // and validated
+    let config = Config::builder()
+        .registry_host("123456789012.dkr.ecr.us-east-1.amazonaws.com")
+        .error_pages(
+            ErrorPages::builder()
+                .default(
+                    Branding::builder()
+                        .json(Template::parse("{\"status\": {{message}}}").unwrap())
+                        .support_link("example.com")
+                        .build(),
+                )
+                .build(),
+        )
+        .build();
+
+    let keys: Vec<_> = config
+        .validate()
+        .into_iter()
+        .filter_map(|e| match e {
+            ConfigError::Invalid { key, .. } => Some(key),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        vec![
+            "error_pages.default.json".to_string(),
+            "error_pages.default.support_link".to_string()
+        ],
+        keys
+    );
+}
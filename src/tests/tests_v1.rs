@@ -1,8 +1,10 @@
+use crate::config::CACHE_MAX_AGE_DEFAULT;
+use crate::error::RewriteError;
+use crate::negotiation::MediaType;
+use crate::oci::{ErrorCode, ErrorResponse};
+use crate::problem::Problem;
 use crate::requests::ApiGatewayRequestType;
-use crate::{
-    CACHE_MAX_AGE_DEFAULT, HTML_ERROR_RESPONSE, JSON_ERROR_RESPONSE, create_error_response,
-    create_rewrite_response, should_return_json,
-};
+use crate::{create_error_response, create_rewrite_response, negotiate_media_type};
 use aws_lambda_events::apigw::ApiGatewayProxyRequest;
 use aws_lambda_events::encodings::Body;
 use aws_lambda_events::http::HeaderValue;
@@ -10,41 +12,64 @@ use aws_lambda_events::query_map::QueryMap;
 use std::collections::HashMap;
 
 #[test]
-fn test_v1_should_return_json() {
+fn test_v1_negotiate_media_type() {
+    let available = [MediaType::Html, MediaType::Json, MediaType::Text];
     let mut req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
 
+    // test empty
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
+
     // test accept json header
     req.headers_mut()
         .insert("Accept", HeaderValue::from_static("application/json"));
 
-    assert!(should_return_json(&req));
-
-    req.headers_mut().remove("Accept");
+    assert_eq!(MediaType::Json, negotiate_media_type(&req, &available));
 
-    // test content type json header
+    // test wildcard, which leaves the choice to us
     req.headers_mut()
-        .insert("Content-Type", HeaderValue::from_static("application/json"));
+        .insert("Accept", HeaderValue::from_static("*/*"));
 
-    assert!(should_return_json(&req));
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
 
-    req.headers_mut().remove("Content-Type");
+    // test q-values
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static("text/html;q=0.5, application/json;q=0.9, */*;q=0.1"),
+    );
 
-    // test empty
-    assert!(!should_return_json(&req));
+    assert_eq!(MediaType::Json, negotiate_media_type(&req, &available));
 
-    // test accept html
+    // test a browser
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+    );
+
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
+
+    // test multiple headers
+    req.headers_mut()
+        .insert("Accept", HeaderValue::from_static("text/html;q=0"));
     req.headers_mut()
-        .insert("Content-Type", HeaderValue::from_static("text/html"));
+        .append("Accept", HeaderValue::from_static("text/*"));
 
-    assert!(!should_return_json(&req));
+    assert_eq!(MediaType::Text, negotiate_media_type(&req, &available));
 
-    req.headers_mut().remove("Content-Type");
+    // test a registry client, which only accepts manifest types
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static(
+            "application/vnd.docker.distribution.manifest.v2+json, application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json",
+        ),
+    );
+
+    assert_eq!(MediaType::Json, negotiate_media_type(&req, &available));
 
-    // test content type html
+    // test nothing acceptable
     req.headers_mut()
-        .insert("Content-Type", HeaderValue::from_static("text/html"));
+        .insert("Accept", HeaderValue::from_static("image/png"));
 
-    assert!(!should_return_json(&req));
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
 }
 
 #[test]
@@ -52,7 +77,7 @@ fn test_v1_create_rewrite_response_cache() {
     let mut req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
     req.set_path("a/b/c");
 
-    let resp = create_rewrite_response(&req, "ecr.registry.com", 12345);
+    let resp = create_rewrite_response(&req, "ecr.registry.com", None, 12345);
 
     assert!(resp.headers().contains_key("Cache-Control"));
     assert_eq!(
@@ -66,7 +91,7 @@ fn test_v1_create_rewrite_response_path() {
     let mut req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
     req.set_path("a/b/c");
 
-    let resp = create_rewrite_response(&req, "ecr.registry.com", CACHE_MAX_AGE_DEFAULT);
+    let resp = create_rewrite_response(&req, "ecr.registry.com", None, CACHE_MAX_AGE_DEFAULT);
 
     assert_eq!(307, resp.status_code());
 
@@ -87,7 +112,7 @@ fn test_v1_create_rewrite_response_path() {
 #[test]
 fn test_v1_create_rewrite_response_no_path() {
     let req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
-    let resp = create_rewrite_response(&req, "ecr.myhost.com", 100);
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 100);
 
     assert!(resp.headers().contains_key("Location"));
     assert_eq!(
@@ -107,7 +132,7 @@ fn test_v1_create_rewrite_response_qs() {
     let mut req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
     *req.query_mut() = qs;
 
-    let resp = create_rewrite_response(&req, "ecr.myhost.com", 60);
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
 
     assert!(resp.headers().contains_key("Location"));
     assert_eq!(
@@ -129,7 +154,7 @@ fn test_v1_create_rewrite_response_qs_extra() {
     req.set_path("/twenty");
     *req.query_mut() = qs;
 
-    let resp = create_rewrite_response(&req, "ecr.myhost.com", 120);
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 120);
 
     assert!(resp.headers().contains_key("Location"));
 
@@ -140,6 +165,42 @@ fn test_v1_create_rewrite_response_qs_extra() {
     assert!(location.contains("c=22"));
 }
 
+#[test]
+fn test_v1_create_rewrite_response_qs_repeated() {
+    let qs = QueryMap::from(HashMap::from([
+        ("n".to_string(), vec!["50".to_string()]),
+        (
+            "last".to_string(),
+            vec!["library/ubuntu".to_string(), "a b".to_string()],
+        ),
+    ]));
+
+    let mut req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
+    req.set_path("/v2/_catalog");
+    *req.multi_value_query_mut().unwrap() = qs;
+
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
+
+    assert_eq!(
+        "https://ecr.myhost.com/v2/_catalog?last=library%2Fubuntu&last=a%20b&n=50",
+        resp.headers().get("Location").unwrap()
+    );
+}
+
+#[test]
+fn test_v1_create_rewrite_response_base_path() {
+    let mut req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
+    req.set_path("/registry/v2/library/ubuntu/tags/list");
+    req.set_stage("prod");
+
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", Some("/registry"), 60);
+
+    assert_eq!(
+        "https://ecr.myhost.com/v2/library/ubuntu/tags/list",
+        resp.headers().get("Location").unwrap()
+    );
+}
+
 #[test]
 fn test_v1_create_error_response() {
     let mut req = ApiGatewayRequestType::V1(ApiGatewayProxyRequest::default());
@@ -151,7 +212,29 @@ fn test_v1_create_error_response() {
 
     // test json body
     match resp.body() {
-        Some(Body::Text(body)) => assert_eq!(JSON_ERROR_RESPONSE, body),
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(ErrorCode::Unknown, body.errors[0].code);
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    // test problem body
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static("application/problem+json"),
+    );
+
+    let resp = create_error_response(&req);
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            let problem: Problem = serde_json::from_str(body).unwrap();
+
+            assert_eq!(500, problem.status);
+            assert_eq!(RewriteError::Misconfigured.problem_type(), problem.type_);
+            assert_eq!(None, problem.instance);
+        }
         _ => panic!("returned non-text body"),
     }
 
@@ -161,7 +244,30 @@ fn test_v1_create_error_response() {
     let resp = create_error_response(&req);
 
     match resp.body() {
-        Some(Body::Text(body)) => assert_eq!(HTML_ERROR_RESPONSE, body),
+        Some(Body::Text(body)) => {
+            assert!(body.contains("<h1>Error: 500 (Internal Server Error)</h1>"));
+            assert!(body.contains("<p>Destination host name not set.</p>"));
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    assert_eq!(
+        "text/html; charset=utf-8",
+        resp.headers().get("Content-Type").unwrap()
+    );
+    assert_eq!("Accept", resp.headers().get("Vary").unwrap());
+
+    // test text body
+    req.headers_mut()
+        .insert("Accept", HeaderValue::from_static("text/plain"));
+
+    let resp = create_error_response(&req);
+
+    match resp.body() {
+        Some(Body::Text(body)) => assert_eq!(
+            "Error: 500 (Internal Server Error)\n\nDestination host name not set.\n",
+            body
+        ),
         _ => panic!("returned non-text body"),
     }
 }
@@ -1,9 +1,11 @@
+use crate::config::CACHE_MAX_AGE_DEFAULT;
+use crate::error::RewriteError;
+use crate::negotiation::MediaType;
+use crate::oci::{ErrorCode, ErrorResponse};
+use crate::problem::Problem;
 use crate::requests::ApiGatewayRequestType;
 use crate::responses::{ApiGatewayGenericResponse, ApiGatewayResponseType};
-use crate::{
-    CACHE_MAX_AGE_DEFAULT, HTML_ERROR_RESPONSE, JSON_ERROR_RESPONSE, create_error_response,
-    create_rewrite_response, should_return_json,
-};
+use crate::{create_error_response, create_rewrite_response, negotiate_media_type};
 use aws_lambda_events::apigw::ApiGatewayV2httpRequest;
 use aws_lambda_events::encodings::Body;
 use aws_lambda_events::http::HeaderValue;
@@ -58,41 +60,64 @@ fn test_v2_override_cookies() {
 }
 
 #[test]
-fn test_v2_should_return_json() {
+fn test_v2_negotiate_media_type() {
+    let available = [MediaType::Html, MediaType::Json, MediaType::Text];
     let mut req = ApiGatewayRequestType::V2(Default::default());
 
+    // test empty
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
+
     // test accept json header
     req.headers_mut()
         .insert("Accept", HeaderValue::from_static("application/json"));
 
-    assert!(should_return_json(&req));
+    assert_eq!(MediaType::Json, negotiate_media_type(&req, &available));
 
-    req.headers_mut().remove("Accept");
-
-    // test content type json header
+    // test wildcard, which leaves the choice to us
     req.headers_mut()
-        .insert("Content-Type", HeaderValue::from_static("application/json"));
+        .insert("Accept", HeaderValue::from_static("*/*"));
+
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
 
-    assert!(should_return_json(&req));
+    // test q-values
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static("text/html;q=0.5, application/json;q=0.9, */*;q=0.1"),
+    );
 
-    req.headers_mut().remove("Content-Type");
+    assert_eq!(MediaType::Json, negotiate_media_type(&req, &available));
 
-    // test empty
-    assert!(!should_return_json(&req));
+    // test a browser
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+    );
 
-    // test accept html
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
+
+    // test multiple headers
+    req.headers_mut()
+        .insert("Accept", HeaderValue::from_static("text/html;q=0"));
     req.headers_mut()
-        .insert("Content-Type", HeaderValue::from_static("text/html"));
+        .append("Accept", HeaderValue::from_static("text/*"));
 
-    assert!(!should_return_json(&req));
+    assert_eq!(MediaType::Text, negotiate_media_type(&req, &available));
 
-    req.headers_mut().remove("Content-Type");
+    // test a registry client, which only accepts manifest types
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static(
+            "application/vnd.docker.distribution.manifest.v2+json, application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.oci.image.manifest.v1+json, application/vnd.oci.image.index.v1+json",
+        ),
+    );
+
+    assert_eq!(MediaType::Json, negotiate_media_type(&req, &available));
 
-    // test content type html
+    // test nothing acceptable
     req.headers_mut()
-        .insert("Content-Type", HeaderValue::from_static("text/html"));
+        .insert("Accept", HeaderValue::from_static("image/png"));
 
-    assert!(!should_return_json(&req));
+    assert_eq!(MediaType::Html, negotiate_media_type(&req, &available));
 }
 
 #[test]
@@ -100,7 +125,7 @@ fn test_v2_create_rewrite_response_cache() {
     let mut req = ApiGatewayRequestType::V2(Default::default());
     req.set_path("a/b/c");
 
-    let resp = create_rewrite_response(&req, "ecr.registry.com", 12345);
+    let resp = create_rewrite_response(&req, "ecr.registry.com", None, 12345);
 
     assert!(resp.headers().contains_key("Cache-Control"));
     assert_eq!(
@@ -114,7 +139,7 @@ fn test_v2_create_rewrite_response_path() {
     let mut req = ApiGatewayRequestType::V2(Default::default());
     req.set_path("a/b/c");
 
-    let resp = create_rewrite_response(&req, "ecr.registry.com", CACHE_MAX_AGE_DEFAULT);
+    let resp = create_rewrite_response(&req, "ecr.registry.com", None, CACHE_MAX_AGE_DEFAULT);
 
     assert_eq!(307, resp.status_code());
 
@@ -135,7 +160,7 @@ fn test_v2_create_rewrite_response_path() {
 #[test]
 fn test_v2_create_rewrite_response_no_path() {
     let req = ApiGatewayRequestType::V2(Default::default());
-    let resp = create_rewrite_response(&req, "ecr.myhost.com", 100);
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 100);
 
     assert!(resp.headers().contains_key("Location"));
     assert_eq!(
@@ -151,7 +176,7 @@ fn test_v2_create_rewrite_response_qs() {
     let mut req = ApiGatewayRequestType::V2(Default::default());
     *req.query_mut() = qs;
 
-    let resp = create_rewrite_response(&req, "ecr.myhost.com", 60);
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
 
     assert!(resp.headers().contains_key("Location"));
     assert_eq!(
@@ -171,7 +196,7 @@ fn test_v2_create_rewrite_response_qs_extra() {
     req.set_path("/twenty");
     *req.query_mut() = qs;
 
-    let resp = create_rewrite_response(&req, "ecr.myhost.com", 120);
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 120);
 
     assert!(resp.headers().contains_key("Location"));
 
@@ -182,6 +207,88 @@ fn test_v2_create_rewrite_response_qs_extra() {
     assert!(location.contains("c=22"));
 }
 
+#[test]
+fn test_v2_create_rewrite_response_raw_qs() {
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/v2/library/ubuntu/tags/list");
+    req.set_raw_query("n=50&last=20.04&n=100&ns=docker.io");
+    // the parsed parameters should not be consulted when the raw query string is present
+    *req.query_mut() = QueryMap::from(HashMap::from([("n".to_string(), "1".to_string())]));
+
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
+
+    assert_eq!(
+        "https://ecr.myhost.com/v2/library/ubuntu/tags/list?n=50&last=20.04&n=100&ns=docker.io",
+        resp.headers().get("Location").unwrap()
+    );
+}
+
+#[test]
+fn test_v2_create_rewrite_response_empty_raw_qs() {
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_raw_query("");
+
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
+
+    assert_eq!(
+        "https://ecr.myhost.com/",
+        resp.headers().get("Location").unwrap()
+    );
+}
+
+#[test]
+fn test_v2_registry_path_stage() {
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/prod/v2/library/ubuntu/manifests/latest");
+    req.set_stage("prod");
+    req.set_domain_name("aaaaaaaaaa.execute-api.us-west-2.amazonaws.com");
+
+    assert_eq!(
+        "/v2/library/ubuntu/manifests/latest",
+        req.registry_path(None)
+    );
+
+    // custom domains do not include the stage in the path
+    req.set_domain_name("docker.mycompany.com");
+    assert_eq!(
+        "/prod/v2/library/ubuntu/manifests/latest",
+        req.registry_path(None)
+    );
+
+    // the default stage is never part of the path
+    req.set_path("/v2/");
+    req.set_stage("$default");
+    req.set_domain_name("aaaaaaaaaa.execute-api.us-west-2.amazonaws.com");
+    assert_eq!("/v2/", req.registry_path(None));
+}
+
+#[test]
+fn test_v2_registry_path_base_path() {
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/registry/v2/");
+    req.set_domain_name("docker.mycompany.com");
+
+    assert_eq!("/v2/", req.registry_path(Some("registry")));
+    assert_eq!("/v2/", req.registry_path(Some("/registry/")));
+    assert_eq!("/registry/v2/", req.registry_path(Some("reg")));
+    assert_eq!("/registry/v2/", req.registry_path(None));
+}
+
+#[test]
+fn test_v2_create_rewrite_response_stage() {
+    let mut req = ApiGatewayRequestType::V2(Default::default());
+    req.set_path("/prod/v2/");
+    req.set_stage("prod");
+    req.set_domain_name("aaaaaaaaaa.execute-api.us-west-2.amazonaws.com");
+
+    let resp = create_rewrite_response(&req, "ecr.myhost.com", None, 60);
+
+    assert_eq!(
+        "https://ecr.myhost.com/v2/",
+        resp.headers().get("Location").unwrap()
+    );
+}
+
 #[test]
 fn test_v2_create_error_response() {
     let mut req = ApiGatewayRequestType::V1(Default::default());
@@ -193,7 +300,29 @@ fn test_v2_create_error_response() {
 
     // test json body
     match resp.body() {
-        Some(Body::Text(body)) => assert_eq!(JSON_ERROR_RESPONSE, body),
+        Some(Body::Text(body)) => {
+            let body: ErrorResponse = serde_json::from_str(body).unwrap();
+            assert_eq!(ErrorCode::Unknown, body.errors[0].code);
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    // test problem body
+    req.headers_mut().insert(
+        "Accept",
+        HeaderValue::from_static("application/problem+json"),
+    );
+
+    let resp = create_error_response(&req);
+
+    match resp.body() {
+        Some(Body::Text(body)) => {
+            let problem: Problem = serde_json::from_str(body).unwrap();
+
+            assert_eq!(500, problem.status);
+            assert_eq!(RewriteError::Misconfigured.problem_type(), problem.type_);
+            assert_eq!(None, problem.instance);
+        }
         _ => panic!("returned non-text body"),
     }
 
@@ -203,7 +332,30 @@ fn test_v2_create_error_response() {
     let resp = create_error_response(&req);
 
     match resp.body() {
-        Some(Body::Text(body)) => assert_eq!(HTML_ERROR_RESPONSE, body),
+        Some(Body::Text(body)) => {
+            assert!(body.contains("<h1>Error: 500 (Internal Server Error)</h1>"));
+            assert!(body.contains("<p>Destination host name not set.</p>"));
+        }
+        _ => panic!("returned non-text body"),
+    }
+
+    assert_eq!(
+        "text/html; charset=utf-8",
+        resp.headers().get("Content-Type").unwrap()
+    );
+    assert_eq!("Accept", resp.headers().get("Vary").unwrap());
+
+    // test text body
+    req.headers_mut()
+        .insert("Accept", HeaderValue::from_static("text/plain"));
+
+    let resp = create_error_response(&req);
+
+    match resp.body() {
+        Some(Body::Text(body)) => assert_eq!(
+            "Error: 500 (Internal Server Error)\n\nDestination host name not set.\n",
+            body
+        ),
         _ => panic!("returned non-text body"),
     }
 }